@@ -0,0 +1,242 @@
+use crate::formula::Reference;
+use crate::parse::{
+    checked_range, convert_pair_to_cell_coord, convert_pair_to_sheet_name, parse_error, LangError,
+    LangParser, Rule,
+};
+use crate::position::{CellPos, CellRange, MAX_COLUMNS};
+use crate::unit::Unit;
+use pest::iterators::{Pair, Pairs};
+use pest::prec_climber::{Assoc, Operator, PrecClimber};
+use pest::Parser;
+
+/// An operator between two values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Concat,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A formula compiled from its source text, so that it can be evaluated
+/// again without parsing. References are to cells of the sheet the formula
+/// is evaluated on unless wrapped in `Sheet`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Quantity(f64, Unit),
+    Text(String),
+    Bool(bool),
+    /// An argument left out, as in `XLOOKUP(1, A1:A5, B1:B5, , -1)`.
+    Omitted,
+    RefError,
+    Cell(CellPos),
+    Range(CellRange),
+    Spill(CellPos),
+    /// A reference on another sheet, such as `Sheet2!A1`.
+    Sheet(String, Box<Expr>),
+    /// A variable or workbook name, uppercased.
+    Name(String),
+    /// A call in either form, `SUM(1, 2)` or `sum 1 2`, uppercased.
+    Call(String, Vec<Expr>),
+    Neg(Box<Expr>),
+    Percent(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+/// Parses and compiles a formula such as `=A1 + 2` entered at `origin`,
+/// which relative R1C1 references such as `R[-1]C` are taken from.
+/// Without a cell, as for names, those references give `#REF!`.
+pub fn compile(source: &str, origin: Option<CellPos>) -> Result<Expr, LangError> {
+    let pairs = LangParser::parse(Rule::ident, source).map_err(parse_error)?;
+    compile_pairs(pairs, origin)
+}
+
+/// Compiles the pairs of a parsed `ident`.
+pub fn compile_pairs(mut pairs: Pairs<Rule>, origin: Option<CellPos>) -> Result<Expr, LangError> {
+    Expr::from_pair(pairs.next().unwrap(), origin)
+}
+
+/// The cell of a `cell` or `r1c1` pair.
+fn cell_at(pair: Pair<Rule>, origin: Option<CellPos>) -> Result<CellPos, LangError> {
+    if pair.as_rule() == Rule::cell {
+        return convert_pair_to_cell_coord(pair);
+    }
+    let source = pair.as_str();
+    let reference = Reference::from_pair(pair, origin.unwrap_or_default())?;
+    let origin = match origin {
+        Some(origin) => origin,
+        None if reference.is_absolute() => CellPos::default(),
+        None => {
+            return Err(LangError::reference(format!(
+                "{} needs a cell to be relative to",
+                source
+            )))
+        }
+    };
+    match reference.resolve(origin) {
+        Some(pos) if pos.x < MAX_COLUMNS => Ok(pos),
+        _ => Err(LangError::reference(format!(
+            "{} is not on the sheet",
+            source
+        ))),
+    }
+}
+
+fn parse_number(source: &str) -> Result<f64, LangError> {
+    source
+        .parse::<f64>()
+        .map_err(|_| LangError::value(format!("{} is not a number", source)))
+}
+
+impl Expr {
+    fn from_pair(pair: Pair<Rule>, origin: Option<CellPos>) -> Result<Self, LangError> {
+        let expr = match pair.as_rule() {
+            Rule::cell | Rule::r1c1 => Expr::Cell(cell_at(pair, origin)?),
+            Rule::range => {
+                let mut inner = pair.into_inner();
+                let start = cell_at(inner.next().unwrap(), origin)?;
+                let end = cell_at(inner.next().unwrap(), origin)?;
+                Expr::Range(checked_range(start, end)?)
+            }
+            Rule::spill => Expr::Spill(cell_at(pair.into_inner().next().unwrap(), origin)?),
+            Rule::sheet_ref => {
+                let mut inner = pair.into_inner();
+                let sheet = convert_pair_to_sheet_name(inner.next().unwrap());
+                Expr::Sheet(
+                    sheet,
+                    Box::new(Expr::from_pair(inner.next().unwrap(), origin)?),
+                )
+            }
+            Rule::name => Expr::Name(pair.as_str().to_ascii_uppercase()),
+            Rule::call | Rule::expr => {
+                let mut inner = pair.into_inner();
+                let name = inner.next().unwrap().as_str().to_ascii_uppercase();
+                let args = inner
+                    .map(|p| Expr::from_pair(p, origin))
+                    .collect::<Result<_, _>>()?;
+                Expr::Call(name, args)
+            }
+            Rule::int | Rule::float => Expr::Number(parse_number(pair.as_str())?),
+            Rule::quantity => {
+                let mut inner = pair.into_inner();
+                let n = parse_number(inner.next().unwrap().as_str())?;
+                Expr::Quantity(n, Unit::named(inner.next().unwrap().as_str())?)
+            }
+            Rule::ref_error => Expr::RefError,
+            Rule::omitted => Expr::Omitted,
+            Rule::boolean => Expr::Bool(pair.as_str().eq_ignore_ascii_case("TRUE")),
+            Rule::string => {
+                let inner = pair.into_inner().next().unwrap().as_str();
+                Expr::Text(inner.replace("\"\"", "\""))
+            }
+            Rule::infix => Expr::from_infix(pair.into_inner(), origin)?,
+            Rule::operand => Expr::from_operand(pair.into_inner(), origin)?,
+            rule => {
+                return Err(LangError::parse(format!(
+                    "Unexpected {:?} in the formula",
+                    rule
+                )))
+            }
+        };
+        Ok(expr)
+    }
+
+    fn from_infix(pairs: Pairs<Rule>, origin: Option<CellPos>) -> Result<Self, LangError> {
+        let climber = PrecClimber::new(vec![
+            Operator::new(Rule::eq, Assoc::Left)
+                | Operator::new(Rule::ne, Assoc::Left)
+                | Operator::new(Rule::lt, Assoc::Left)
+                | Operator::new(Rule::le, Assoc::Left)
+                | Operator::new(Rule::gt, Assoc::Left)
+                | Operator::new(Rule::ge, Assoc::Left),
+            Operator::new(Rule::concat, Assoc::Left),
+            Operator::new(Rule::add, Assoc::Left) | Operator::new(Rule::sub, Assoc::Left),
+            Operator::new(Rule::mul, Assoc::Left) | Operator::new(Rule::div, Assoc::Left),
+            Operator::new(Rule::pow, Assoc::Left),
+        ]);
+
+        let primary = |pair| Expr::from_pair(pair, origin);
+        climber.climb(pairs, primary, |lhs, op, rhs| {
+            let op = match op.as_rule() {
+                Rule::add => BinOp::Add,
+                Rule::sub => BinOp::Sub,
+                Rule::mul => BinOp::Mul,
+                Rule::div => BinOp::Div,
+                Rule::pow => BinOp::Pow,
+                Rule::concat => BinOp::Concat,
+                Rule::eq => BinOp::Eq,
+                Rule::ne => BinOp::Ne,
+                Rule::lt => BinOp::Lt,
+                Rule::le => BinOp::Le,
+                Rule::gt => BinOp::Gt,
+                _ => BinOp::Ge,
+            };
+            Ok(Expr::Binary(op, Box::new(lhs?), Box::new(rhs?)))
+        })
+    }
+
+    /// Negation applies after any `%`, so `-50%` is -0.5.
+    fn from_operand(pairs: Pairs<Rule>, origin: Option<CellPos>) -> Result<Self, LangError> {
+        let mut negate = false;
+        let mut expr = Expr::Omitted;
+        for pair in pairs {
+            match pair.as_rule() {
+                Rule::neg => negate = !negate,
+                Rule::percent => expr = Expr::Percent(Box::new(expr)),
+                _ => expr = Expr::from_pair(pair, origin)?,
+            }
+        }
+
+        if negate {
+            expr = Expr::Neg(Box::new(expr));
+        }
+        Ok(expr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile() {
+        let expr = compile("=-A1% + 2 * SUM(B1:B2, Sheet2!C3)", None).unwrap();
+        let args = vec![
+            Expr::Range(CellRange::new(CellPos::new(1, 0), CellPos::new(1, 1))),
+            Expr::Sheet(
+                "Sheet2".to_string(),
+                Box::new(Expr::Cell(CellPos::new(2, 2))),
+            ),
+        ];
+        let product = Expr::Binary(
+            BinOp::Mul,
+            Box::new(Expr::Number(2.0)),
+            Box::new(Expr::Call("SUM".to_string(), args)),
+        );
+        let negated = Expr::Neg(Box::new(Expr::Percent(Box::new(Expr::Cell(CellPos::new(
+            0, 0,
+        ))))));
+        assert_eq!(
+            expr,
+            Expr::Binary(BinOp::Add, Box::new(negated), Box::new(product))
+        );
+
+        assert_eq!(
+            compile("=add 1 (x)", None),
+            Ok(Expr::Call(
+                "ADD".to_string(),
+                vec![Expr::Number(1.0), Expr::Name("X".to_string())]
+            ))
+        );
+        assert!(compile("=add (1", None).is_err());
+    }
+}
@@ -1,10 +1,18 @@
 use tui::layout::Rect;
 
 use crate::data::Data;
-use crate::position::CellPos;
+use crate::parse::{check_syntax, SyntaxError};
+use crate::position::{column_name, row_number, CellPos};
 use crate::state::{StateInfo, StateVal};
+use crate::value::Value;
 use crate::viewer::{Item, HEIGHT_LABEL_MARGIN};
-use log::debug;
+
+const EDIT_HEIGHT: u16 = 10;
+const STATUS_HEIGHT: u16 = 1;
+
+fn is_editing(mode: StateVal) -> bool {
+    mode == StateVal::Insert || mode == StateVal::Command
+}
 
 pub struct Compositor {
     scroll_offset: CellPos,
@@ -13,6 +21,13 @@ pub struct Compositor {
     default_height: u16,
     area: Option<Rect>,
     drawable_data: Vec<(CellPos, String)>,
+    /// Why the cell under the cursor holds an error, if it does.
+    cursor_error: Option<String>,
+    /// The sheets of the workbook with the active one in brackets, when
+    /// there is more than one.
+    sheet_tabs: String,
+    /// Why the formula being typed does not parse, if it does not.
+    syntax_error: Option<SyntaxError>,
     state: Option<StateInfo>,
 }
 
@@ -24,6 +39,9 @@ impl Default for Compositor {
             default_width: 12,
             default_height: 1,
             drawable_data: Vec::new(),
+            cursor_error: None,
+            sheet_tabs: String::new(),
+            syntax_error: None,
             state: None,
             area: None,
         }
@@ -42,6 +60,11 @@ impl Compositor {
     pub fn set_state(&mut self, state: StateInfo) {
         self.cursor_pos = state.cursor_pos;
         self.handle_scrolling();
+        self.syntax_error = if state.mode == StateVal::Insert && state.buffer.starts_with('=') {
+            check_syntax(&state.buffer).err()
+        } else {
+            None
+        };
         self.state = Some(state);
     }
 
@@ -51,11 +74,32 @@ impl Compositor {
             for y in (0..self.get_n_high()).map(|y| y + self.scroll_offset.y) {
                 let cell_pos = CellPos::new(x, y);
                 if let Some(d) = data.get(cell_pos) {
-                    drawable_data.push((cell_pos, d.clone()));
+                    drawable_data.push((cell_pos, d.to_string()));
                 }
             }
         }
         self.drawable_data = drawable_data;
+        self.cursor_error = match data.get(self.cursor_pos) {
+            Some(Value::Error(e)) => Some(format!("{}: {}", e, e.reason)),
+            _ => None,
+        };
+
+        let sheets = data.sheets();
+        self.sheet_tabs = if sheets.len() > 1 {
+            let tabs: Vec<_> = sheets
+                .iter()
+                .map(|s| {
+                    if s.id() == data.active_sheet() {
+                        format!("[{}]", s.name())
+                    } else {
+                        s.name().to_string()
+                    }
+                })
+                .collect();
+            tabs.join(" ")
+        } else {
+            String::new()
+        };
     }
 
     pub fn get_widths(&self) -> Vec<u16> {
@@ -71,15 +115,14 @@ impl Compositor {
     pub fn get_labels(&self) -> (Vec<String>, Vec<String>) {
         let width_labels: Vec<_> = (0..self.get_n_wide())
             .map(|x| x + self.scroll_offset.x)
-            .map(|x| (x as u8 + 65) as char)
-            .map(|x| format!("{}", x))
+            .map(column_name)
             .collect();
 
         let height_labels: Vec<_> = (0..self.get_n_high())
-            .map(|x| x + self.scroll_offset.y)
-            .map(|x| format!("{}", x))
+            .map(|y| row_number(y + self.scroll_offset.y))
+            .map(|y| y.to_string())
             .collect();
-        return (width_labels, height_labels);
+        (width_labels, height_labels)
     }
 
     pub fn get_top_left(&self) -> (bool, bool) {
@@ -113,15 +156,16 @@ impl Compositor {
             items.push(item);
         }
 
-        return items;
+        items
     }
 
     pub fn get_area_cells(&self) -> Rect {
         if let (Some(state), Some(area)) = (&self.state, self.area) {
-            if state.mode == StateVal::Insert {
-                Rect::new(area.left(), area.top(), area.width, area.height - 10)
+            let height = area.height - STATUS_HEIGHT;
+            if is_editing(state.mode) {
+                Rect::new(area.left(), area.top(), area.width, height - EDIT_HEIGHT)
             } else {
-                area
+                Rect::new(area.left(), area.top(), area.width, height)
             }
         } else {
             Rect::new(0, 0, 0, 0)
@@ -130,8 +174,9 @@ impl Compositor {
 
     pub fn get_area_edit(&self) -> Option<Rect> {
         if let (Some(state), Some(area)) = (&self.state, self.area) {
-            if state.mode == StateVal::Insert {
-                Some(Rect::new(area.left(), area.bottom() - 10, area.width, 10))
+            if is_editing(state.mode) {
+                let top = area.bottom() - STATUS_HEIGHT - EDIT_HEIGHT;
+                Some(Rect::new(area.left(), top, area.width, EDIT_HEIGHT))
             } else {
                 None
             }
@@ -140,11 +185,41 @@ impl Compositor {
         }
     }
 
+    pub fn get_area_status(&self) -> Rect {
+        if let Some(area) = self.area {
+            let top = area.bottom() - STATUS_HEIGHT;
+            Rect::new(area.left(), top, area.width, STATUS_HEIGHT)
+        } else {
+            Rect::new(0, 0, 0, 0)
+        }
+    }
+
+    pub fn get_edit_title(&self) -> &str {
+        match &self.state {
+            Some(state) if state.mode == StateVal::Command => "Command",
+            _ => "Edit",
+        }
+    }
+
     pub fn get_buffer(&self) -> &str {
         if let Some(state) = &self.state {
             &state.buffer
         } else {
-            &""
+            ""
+        }
+    }
+
+    pub fn get_syntax_error(&self) -> Option<&SyntaxError> {
+        self.syntax_error.as_ref()
+    }
+
+    /// The status message, or else the reason for an error in the cell
+    /// under the cursor, or else the sheets of the workbook.
+    pub fn get_status(&self) -> &str {
+        match (&self.state, &self.cursor_error) {
+            (Some(state), _) if !state.status.is_empty() => &state.status,
+            (_, Some(reason)) => reason,
+            _ => &self.sheet_tabs,
         }
     }
 
@@ -1,22 +1,189 @@
-use crate::graph::Dag;
-use crate::parse::{parse, LangError};
-use crate::position::CellPos;
-use crate::viewer::Item;
+use crate::ast::Expr;
+use crate::formula::{Formula, Notation};
+use crate::functions::{Function, Registry};
+use crate::graph::{Dag, Node, Step};
+use crate::parse::{
+    convert_cell_coord_to_str, evaluate, is_valid_name, parse_name_target, quote_sheet_name,
+    LangError,
+};
+use crate::position::{CellPos, CellRange, SheetId};
+use crate::value::Value;
 use log::debug;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 
-pub struct Data {
+/// What a workbook name refers to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NameTarget {
+    Cell(SheetId, CellPos),
+    Range(SheetId, CellRange),
+    /// A formula evaluated wherever the name is used, such as a LAMBDA,
+    /// with its source.
+    Formula(String, Rc<Expr>),
+}
+
+/// One sheet of a workbook: the text entered into its cells and the values
+/// they calculate to.
+pub struct Sheet {
+    id: SheetId,
+    name: String,
     cell_data: HashMap<CellPos, String>,
-    calculated: HashMap<CellPos, String>,
-    dag: Dag,
+    formulas: HashMap<CellPos, Formula>,
+    calculated: HashMap<CellPos, Value>,
+    /// The formula cell each cell of a spilled array belongs to.
+    spills: HashMap<CellPos, CellPos>,
+    /// The cells each array formula spills, or would spill, into.
+    spill_ranges: HashMap<CellPos, CellRange>,
 }
 
-impl Default for Data {
-    fn default() -> Self {
+impl Sheet {
+    fn new(id: SheetId, name: &str) -> Self {
         Self {
+            id,
+            name: name.to_string(),
             cell_data: HashMap::new(),
+            formulas: HashMap::new(),
             calculated: HashMap::new(),
+            spills: HashMap::new(),
+            spill_ranges: HashMap::new(),
+        }
+    }
+
+    pub fn id(&self) -> SheetId {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The text entered into a cell, with formulas rendered for that cell.
+    pub fn get_source(&self, location: CellPos) -> Option<String> {
+        if let Some(formula) = self.formulas.get(&location) {
+            Some(formula.to_source(location))
+        } else {
+            self.cell_data.get(&location).cloned()
+        }
+    }
+
+    /// The value shown in a cell, which may have spilled from an array
+    /// formula above or to the left of it.
+    pub fn get(&self, location: CellPos) -> Option<&Value> {
+        if let Some(anchor) = self.spills.get(&location) {
+            if let Some(Value::Array(rows)) = self.calculated.get(anchor) {
+                let offset = location - *anchor;
+                return rows.get(offset.y).and_then(|row| row.get(offset.x));
+            }
+        }
+        self.calculated.get(&location)
+    }
+
+    /// The whole array spilled from `anchor`, as referred to by `A1#`.
+    pub fn get_spill(&self, anchor: CellPos) -> Option<&Value> {
+        match self.spills.get(&anchor) {
+            Some(pos) if *pos == anchor => self.calculated.get(&anchor),
+            _ => None,
+        }
+    }
+
+    /// Stores the value of a formula. An array spills into the cells below
+    /// and to the right, unless one of them is already occupied which gives
+    /// `#SPILL!`. Returns the other cells whose values changed.
+    fn set_calculated(&mut self, location: CellPos, val: Value) -> Vec<CellPos> {
+        let mut changed = self.clear_spill(location);
+        let val = match val {
+            Value::Array(mut rows) => {
+                let height = rows.len();
+                let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+                if height == 0 || width == 0 {
+                    Value::Error(LangError::value("The array is empty"))
+                } else if height == 1 && width == 1 {
+                    rows.remove(0).remove(0)
+                } else {
+                    let end = location + CellPos::new(width - 1, height - 1);
+                    let range = CellRange::new(location, end);
+                    self.spill_ranges.insert(location, range);
+                    if range
+                        .iter()
+                        .any(|pos| pos != location && self.is_occupied(pos))
+                    {
+                        Value::Error(LangError::spill("Another cell is in the way of the array"))
+                    } else {
+                        for pos in range.iter() {
+                            self.spills.insert(pos, location);
+                        }
+                        changed.extend(range.iter().filter(|pos| *pos != location));
+                        Value::Array(rows)
+                    }
+                }
+            }
+            val => val,
+        };
+        self.calculated.insert(location, val);
+        changed
+    }
+
+    /// Removes the array spilled from `anchor`, returning the cells it
+    /// covered apart from the anchor itself.
+    fn clear_spill(&mut self, anchor: CellPos) -> Vec<CellPos> {
+        let range = match self.spill_ranges.remove(&anchor) {
+            Some(range) => range,
+            None => return Vec::new(),
+        };
+        let cells: Vec<_> = range
+            .iter()
+            .filter(|pos| self.spills.get(pos) == Some(&anchor))
+            .collect();
+        for pos in &cells {
+            self.spills.remove(pos);
+        }
+        cells.into_iter().filter(|pos| *pos != anchor).collect()
+    }
+
+    fn is_occupied(&self, pos: CellPos) -> bool {
+        self.formulas.contains_key(&pos)
+            || self.cell_data.get(&pos).is_some_and(|s| !s.is_empty())
+            || self.spills.contains_key(&pos)
+    }
+
+    /// The array formulas other than `except` that spill, or would spill,
+    /// into any of `cells`.
+    fn spills_over<'a>(
+        &'a self,
+        cells: &'a [CellPos],
+        except: CellPos,
+    ) -> impl Iterator<Item = CellPos> + 'a {
+        self.spill_ranges
+            .iter()
+            .filter(move |(anchor, range)| {
+                **anchor != except && cells.iter().any(|pos| range.contains(*pos))
+            })
+            .map(|(anchor, _)| *anchor)
+    }
+}
+
+/// A workbook of one or more sheets. Cells are read and written on the
+/// active sheet unless a sheet is given.
+pub struct Data {
+    sheets: Vec<Sheet>,
+    active: SheetId,
+    next_sheet: usize,
+    names: HashMap<String, NameTarget>,
+    functions: Registry,
+    dag: Dag<Node>,
+    notation: Notation,
+}
+
+impl Default for Data {
+    fn default() -> Self {
+        Self {
+            sheets: vec![Sheet::new(SheetId(0), "Sheet1")],
+            active: SheetId(0),
+            next_sheet: 1,
+            names: HashMap::new(),
+            functions: Registry::default(),
             dag: Dag::new(),
+            notation: Notation::default(),
         }
     }
 }
@@ -27,54 +194,672 @@ impl Data {
     }
 
     pub fn insert(&mut self, location: CellPos, value: String) {
-        self.calculated.remove(&location);
-        if value.starts_with("=") {
-            self.dag.remove(location);
-            let out = parse(&value, &self);
-            match out {
-                Ok((val, deps)) => {
-                    self.calculated.insert(location, val.to_string());
-
-                    debug!("Calculated Cell Data {:?}: {}", location, &val);
-                    self.dag.insert(location, &deps);
-                }
-                Err(e) => {
-                    self.calculated.insert(location, e.to_string());
+        self.insert_in(self.active, location, value);
+    }
+
+    /// Sets a cell of the given sheet and recalculates everything depending
+    /// on it, on any sheet.
+    pub fn insert_in(&mut self, sheet: SheetId, location: CellPos, value: String) {
+        let node = Node::Cell(sheet, location);
+        let formula = if value.starts_with('=') {
+            let formula = Formula::new(&value, location);
+            let out = self.calculate(&formula, sheet);
+            Some((formula, out))
+        } else {
+            None
+        };
+
+        // Borrow only the sheets, leaving the graph free to update
+        let this = self.sheets.iter_mut().find(|s| s.id == sheet).unwrap();
+        let mut changed = this.clear_spill(location);
+        this.calculated.remove(&location);
+        this.cell_data.remove(&location);
+        this.formulas.remove(&location);
+        debug!("Raw Cell Data {:?}: {}", location, &value);
+
+        match formula {
+            Some((formula, (val, deps))) => {
+                debug!("Calculated Cell Data {:?}: {}", location, &val);
+                this.formulas.insert(location, formula);
+                changed.extend(this.set_calculated(location, val));
+                self.dag.insert(node.clone(), &deps);
+            }
+            None => {
+                this.calculated.insert(location, Value::from_input(&value));
+                this.cell_data.insert(location, value);
+                self.dag.insert(node.clone(), &[]);
+            }
+        }
+
+        // Arrays spilling over this cell are blocked or freed by it, so they
+        // go first for its dependents to see what is left in it
+        let mut nodes: Vec<_> = self
+            .sheet(sheet)
+            .spills_over(&[location], location)
+            .map(|pos| Node::Cell(sheet, pos))
+            .collect();
+        nodes.push(node);
+        nodes.extend(changed.into_iter().map(|pos| Node::Cell(sheet, pos)));
+        self.update_using_dag(nodes);
+    }
+
+    /// The sheets of the workbook in order.
+    pub fn sheets(&self) -> &[Sheet] {
+        &self.sheets
+    }
+
+    /// The sheet with the given id, which must not have been deleted.
+    pub fn sheet(&self, id: SheetId) -> &Sheet {
+        self.sheets.iter().find(|s| s.id == id).unwrap()
+    }
+
+    fn sheet_mut(&mut self, id: SheetId) -> &mut Sheet {
+        self.sheets.iter_mut().find(|s| s.id == id).unwrap()
+    }
+
+    /// Finds a sheet by name, ignoring case.
+    pub fn sheet_id(&self, name: &str) -> Option<SheetId> {
+        self.sheets
+            .iter()
+            .find(|s| s.name.eq_ignore_ascii_case(name))
+            .map(|s| s.id)
+    }
+
+    pub fn active_sheet(&self) -> SheetId {
+        self.active
+    }
+
+    pub fn set_active_sheet(&mut self, id: SheetId) {
+        self.active = id;
+    }
+
+    /// Adds a sheet after the others. Formulas already referring to a
+    /// sheet of that name pick it up.
+    pub fn add_sheet(&mut self, name: &str) -> Result<SheetId, LangError> {
+        let name = self.check_sheet_name(name, None)?;
+        let id = SheetId(self.next_sheet);
+        self.next_sheet += 1;
+        self.sheets.push(Sheet::new(id, &name));
+        self.reenter_formulas(|formula| formula.refers_to_sheet(&name));
+        Ok(id)
+    }
+
+    /// Renames a sheet, rewriting the formulas that refer to it.
+    pub fn rename_sheet(&mut self, id: SheetId, name: &str) -> Result<(), LangError> {
+        let name = self.check_sheet_name(name, Some(id))?;
+        let old = std::mem::replace(&mut self.sheet_mut(id).name, name.clone());
+        // Formulas that referred to a missing sheet of the new name now
+        // find this one as well
+        self.reenter_formulas(|formula| {
+            formula.rename_sheet(&old, &name);
+            formula.refers_to_sheet(&name)
+        });
+        Ok(())
+    }
+
+    /// Deletes a sheet. References to it elsewhere become `#REF!`, as do
+    /// names pointing into it. The last sheet cannot be deleted.
+    pub fn delete_sheet(&mut self, id: SheetId) -> Result<(), LangError> {
+        if self.sheets.len() == 1 {
+            return Err(LangError::reference("The last sheet cannot be deleted"));
+        }
+        let index = self.sheets.iter().position(|s| s.id == id).unwrap();
+        let sheet = self.sheets.remove(index);
+        if self.active == id {
+            self.active = self.sheets[index.min(self.sheets.len() - 1)].id;
+        }
+
+        let cells = sheet
+            .cell_data
+            .keys()
+            .chain(sheet.formulas.keys())
+            .chain(sheet.calculated.keys());
+        for pos in cells {
+            self.dag.remove(Node::Cell(id, *pos));
+        }
+
+        let names: Vec<_> = self
+            .names
+            .iter()
+            .filter(|(_, target)| match target {
+                NameTarget::Cell(s, _) | NameTarget::Range(s, _) => *s == id,
+                NameTarget::Formula(..) => false,
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in names {
+            let target = NameTarget::Formula("=#REF!".to_string(), Rc::new(Expr::RefError));
+            self.names.insert(name.clone(), target);
+            self.dag.insert(Node::Name(name.clone()), &[]);
+            self.update_using_dag(vec![Node::Name(name)]);
+        }
+
+        self.reenter_formulas(|formula| formula.remove_sheet(&sheet.name));
+        Ok(())
+    }
+
+    /// Trims a new sheet name and checks that no sheet other than `except`
+    /// already has it.
+    fn check_sheet_name(&self, name: &str, except: Option<SheetId>) -> Result<String, LangError> {
+        let name = name.trim();
+        if name.is_empty() || name.contains('!') {
+            return Err(LangError::name(format!(
+                "\"{}\" is not a valid sheet name",
+                name
+            )));
+        }
+        match self.sheet_id(name) {
+            Some(id) if Some(id) != except => Err(LangError::name(format!(
+                "There is already a sheet {}",
+                name
+            ))),
+            _ => Ok(name.to_string()),
+        }
+    }
+
+    /// Enters the formulas for which `update` returns true again, on every
+    /// sheet, so that their references are looked up afresh. `update` may
+    /// rewrite the formula first.
+    fn reenter_formulas(&mut self, mut update: impl FnMut(&mut Formula) -> bool) {
+        let mut cells = Vec::new();
+        for sheet in &mut self.sheets {
+            for (pos, formula) in sheet.formulas.iter_mut() {
+                if update(formula) {
+                    cells.push((sheet.id, *pos, formula.to_source(*pos)));
                 }
             }
         }
+        for (sheet, pos, source) in cells {
+            self.insert_in(sheet, pos, source);
+        }
+    }
 
-        debug!("Raw Cell Data {:?}: {}", location, &value);
-        self.cell_data.insert(location, value);
-        self.update_using_dag(location);
+    /// Points the workbook name `name` at a cell or range such as `B2`,
+    /// `A1:A10` or `Sheet2!A1`, or at a formula such as `=LAMBDA(x, x*2)`,
+    /// recalculating every formula that uses it. A name holding a LAMBDA
+    /// can be called like a function.
+    pub fn define_name(&mut self, name: &str, target: &str) -> Result<(), LangError> {
+        if !is_valid_name(name) {
+            return Err(LangError::name(format!("\"{}\" is not a valid name", name)));
+        }
+        let target = parse_name_target(target, self)?;
+        // Formulas are evaluated where the name is used, so their
+        // references become dependencies of the cells using them instead.
+        let cells: Vec<_> = match &target {
+            NameTarget::Cell(sheet, pos) => vec![Node::Cell(*sheet, *pos)],
+            NameTarget::Range(sheet, range) => {
+                range.iter().map(|pos| Node::Cell(*sheet, pos)).collect()
+            }
+            NameTarget::Formula(..) => Vec::new(),
+        };
+
+        let name = name.to_ascii_uppercase();
+        debug!("Defined Name {}: {:?}", name, target);
+        self.names.insert(name.clone(), target);
+        self.dag.insert(Node::Name(name.clone()), &cells);
+        self.update_using_dag(vec![Node::Name(name)]);
+        Ok(())
+    }
+
+    /// Removes a workbook name, returning whether it existed.
+    pub fn delete_name(&mut self, name: &str) -> bool {
+        let name = name.to_ascii_uppercase();
+        if self.names.remove(&name).is_none() {
+            return false;
+        }
+
+        self.dag.insert(Node::Name(name.clone()), &[]);
+        self.update_using_dag(vec![Node::Name(name)]);
+        true
+    }
+
+    pub fn get_name(&self, name: &str) -> Option<&NameTarget> {
+        self.names.get(&name.to_ascii_uppercase())
+    }
+
+    /// Every workbook name with its target as it would be entered on the
+    /// active sheet, sorted by name.
+    pub fn names(&self) -> Vec<(&String, String)> {
+        let mut names: Vec<_> = self
+            .names
+            .iter()
+            .map(|(name, target)| (name, self.describe_target(target)))
+            .collect();
+        names.sort_by(|a, b| a.0.cmp(b.0));
+        names
+    }
+
+    fn describe_target(&self, target: &NameTarget) -> String {
+        let (sheet, cells) = match target {
+            NameTarget::Cell(sheet, pos) => (*sheet, convert_cell_coord_to_str(*pos)),
+            NameTarget::Range(sheet, range) => (
+                *sheet,
+                format!(
+                    "{}:{}",
+                    convert_cell_coord_to_str(range.start),
+                    convert_cell_coord_to_str(range.end)
+                ),
+            ),
+            NameTarget::Formula(source, _) => return source.clone(),
+        };
+        if sheet == self.active {
+            cells
+        } else {
+            format!("{}!{}", quote_sheet_name(self.sheet(sheet).name()), cells)
+        }
+    }
+
+    /// The text entered into a cell of the active sheet, with formulas
+    /// shown in the workbook's notation.
+    pub fn get_source(&self, location: CellPos) -> Option<String> {
+        let sheet = self.sheet(self.active);
+        match sheet.formulas.get(&location) {
+            Some(formula) => Some(formula.to_source_in(location, self.notation)),
+            None => sheet.get_source(location),
+        }
     }
 
-    pub fn get(&self, location: CellPos) -> Option<&String> {
-        if let Some(val) = self.calculated.get(&location) {
-            Some(val)
+    pub fn notation(&self) -> Notation {
+        self.notation
+    }
+
+    /// Switches how formulas are shown. Either notation can be entered.
+    pub fn set_notation(&mut self, notation: Notation) {
+        self.notation = notation;
+    }
+
+    /// Copies a cell of the active sheet, shifting the relative references
+    /// of a formula by the distance between the two cells.
+    pub fn copy(&mut self, from: CellPos, to: CellPos) {
+        let sheet = self.sheet(self.active);
+        let value = if let Some(formula) = sheet.formulas.get(&from) {
+            formula.to_source(to)
         } else {
-            self.cell_data.get(&location)
+            sheet.cell_data.get(&from).cloned().unwrap_or_default()
+        };
+        self.insert(to, value);
+    }
+
+    /// Copies a cell into every other cell of `range`.
+    pub fn fill(&mut self, from: CellPos, range: CellRange) {
+        for to in range.iter().filter(|to| *to != from) {
+            self.copy(from, to);
         }
     }
 
-    fn update_using_dag(&mut self, cell: CellPos) {
-        let traversal = self.dag.get_topological_sort(cell).unwrap();
-        debug!("Traversal of length {}", traversal.len());
-        for dep in traversal {
-            let val = self.cell_data.get(&dep).unwrap();
-            if !val.starts_with("=") {
+    /// Makes a function callable from formulas, replacing any built-in of
+    /// the same name. Existing cells are not recalculated.
+    pub fn register_function<F: Function + 'static>(&mut self, function: F) {
+        self.functions.register(function);
+    }
+
+    pub fn get_function(&self, name: &str) -> Option<&dyn Function> {
+        self.functions.get(name)
+    }
+
+    /// The value shown in a cell of the active sheet.
+    pub fn get(&self, location: CellPos) -> Option<&Value> {
+        self.sheet(self.active).get(location)
+    }
+
+    /// The value shown in a cell of the given sheet.
+    pub fn get_in(&self, sheet: SheetId, location: CellPos) -> Option<&Value> {
+        self.sheet(sheet).get(location)
+    }
+
+    /// The whole array spilled from `anchor` on the active sheet.
+    pub fn get_spill(&self, anchor: CellPos) -> Option<&Value> {
+        self.sheet(self.active).get_spill(anchor)
+    }
+
+    /// The value of a formula on `sheet` and the cells and names it depends
+    /// on, which are known even when it evaluates to an error.
+    fn calculate(&self, formula: &Formula, sheet: SheetId) -> (Value, Vec<Node>) {
+        match formula.expr() {
+            Ok(expr) => {
+                let (val, deps) = evaluate(&expr, self, sheet);
+                (val.unwrap_or_else(Value::Error), deps)
+            }
+            Err(e) => (Value::Error(e), Vec::new()),
+        }
+    }
+
+    /// Names the nodes of a cycle for the status line, as in `A1 → B1 → A1`.
+    /// Cells on other sheets than the first are qualified.
+    fn describe_cycle(&self, cycle: &[Node]) -> String {
+        let first = match cycle.first() {
+            Some(Node::Cell(sheet, _)) => Some(*sheet),
+            _ => None,
+        };
+        let names: Vec<_> = cycle
+            .iter()
+            .map(|node| match node {
+                Node::Cell(sheet, pos) if Some(*sheet) == first => convert_cell_coord_to_str(*pos),
+                Node::Cell(sheet, pos) => format!(
+                    "{}!{}",
+                    quote_sheet_name(self.sheet(*sheet).name()),
+                    convert_cell_coord_to_str(*pos)
+                ),
+                Node::Name(name) => name.clone(),
+            })
+            .collect();
+        names.join(" → ")
+    }
+
+    /// Recalculates everything depending on `nodes`. When an array changes
+    /// size the cells it spills into, and other arrays that spill over
+    /// them, are recalculated as well.
+    fn update_using_dag(&mut self, nodes: Vec<Node>) {
+        let mut pending = nodes;
+        pending.reverse();
+        let mut visited = HashSet::new();
+
+        while let Some(node) = pending.pop() {
+            if !visited.insert(node.clone()) {
                 continue;
             }
+            let traversal = self.dag.get_topological_sort(node);
+            debug!("Traversal of length {}", traversal.len());
+            for step in traversal {
+                let (sheet, dep) = match step {
+                    Step::Node(Node::Cell(sheet, pos)) => (sheet, pos),
+                    Step::Node(Node::Name(_)) => continue,
+                    Step::Cycle(cycle) => {
+                        // Cells using the cycle come later and pick up its errors
+                        let path = self.describe_cycle(&cycle);
+                        debug!("Cycle: {}", path);
+                        for node in &cycle[1..] {
+                            if let Node::Cell(sheet, pos) = *node {
+                                let err = Value::Error(LangError::cycle(path.clone()));
+                                let changed = self.sheet_mut(sheet).set_calculated(pos, err);
+                                pending
+                                    .extend(changed.into_iter().map(|pos| Node::Cell(sheet, pos)));
+                            }
+                        }
+                        continue;
+                    }
+                };
+                let this = self.sheets.iter().find(|s| s.id == sheet);
+                let (val, deps) = match this.and_then(|s| s.formulas.get(&dep)) {
+                    Some(formula) => self.calculate(formula, sheet),
+                    None => continue,
+                };
 
-            let out = parse(&val, &self);
-            match out {
-                Ok((val, _)) => {
-                    self.calculated.insert(dep, val.to_string());
-                }
-                Err(e) => {
-                    self.calculated.insert(dep, e.to_string());
+                // An error may have hidden references until now
+                self.dag.insert(Node::Cell(sheet, dep), &deps);
+                let this = self.sheet_mut(sheet);
+                let changed = this.set_calculated(dep, val);
+                let anchors: Vec<_> = this.spills_over(&changed, dep).collect();
+                pending.extend(changed.into_iter().map(|pos| Node::Cell(sheet, pos)));
+                pending.extend(anchors.into_iter().map(|pos| Node::Cell(sheet, pos)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{parse, ErrorKind};
+
+    #[test]
+    fn test_copy_and_fill() {
+        let mut data = Data::new();
+        for y in 0..3 {
+            data.insert(CellPos::new(0, y), (y + 1).to_string());
+        }
+        data.insert(CellPos::new(1, 0), "=A1*$A$1*10".to_string());
+
+        data.copy(CellPos::new(1, 0), CellPos::new(1, 1));
+        assert_eq!(
+            data.get_source(CellPos::new(1, 1)),
+            Some("=A2*$A$1*10".to_string())
+        );
+        assert_eq!(data.get(CellPos::new(1, 1)), Some(&Value::Number(20.0)));
+
+        let range = CellRange::new(CellPos::new(1, 0), CellPos::new(1, 2));
+        data.fill(CellPos::new(1, 0), range);
+        assert_eq!(data.get(CellPos::new(1, 2)), Some(&Value::Number(30.0)));
+
+        data.copy(CellPos::new(0, 0), CellPos::new(2, 0));
+        assert_eq!(data.get_source(CellPos::new(2, 0)), Some("1".to_string()));
+    }
+
+    #[test]
+    fn test_r1c1() {
+        let mut data = Data::new();
+        data.insert(CellPos::new(0, 0), "2".to_string());
+        data.insert(CellPos::new(0, 1), "=R[-1]C*10".to_string());
+        data.insert(CellPos::new(1, 1), "=SUM(R1C1:RC[-1])".to_string());
+        assert_eq!(data.get(CellPos::new(0, 1)), Some(&Value::Number(20.0)));
+        assert_eq!(data.get(CellPos::new(1, 1)), Some(&Value::Number(22.0)));
+
+        data.insert(CellPos::new(0, 0), "3".to_string());
+        assert_eq!(data.get(CellPos::new(1, 1)), Some(&Value::Number(33.0)));
+        assert_eq!(
+            data.get_source(CellPos::new(1, 1)),
+            Some("=SUM($A$1:A2)".to_string())
+        );
+
+        data.set_notation(Notation::R1C1);
+        assert_eq!(
+            data.get_source(CellPos::new(0, 1)),
+            Some("=R[-1]C*10".to_string())
+        );
+        assert_eq!(
+            data.get_source(CellPos::new(1, 1)),
+            Some("=SUM(R1C1:RC[-1])".to_string())
+        );
+
+        let off_sheet = [
+            "=R[-1]C",
+            "=R99999999999999999999999C1",
+            "=R[99999999999999999999999]C",
+        ];
+        for source in off_sheet.iter() {
+            data.insert(CellPos::new(2, 0), source.to_string());
+            assert_eq!(
+                data.get(CellPos::new(2, 0)),
+                Some(&Value::Error(ErrorKind::Ref.into())),
+                "{}",
+                source
+            );
+        }
+
+        // Names and plain formulas have no cell to be relative to
+        assert_eq!(
+            data.define_name("above", "=R[-1]C"),
+            Err(ErrorKind::Ref.into())
+        );
+        assert_eq!(parse("=RC[1]", &data), Err(ErrorKind::Ref.into()));
+        assert_eq!(
+            parse("=R1C1*2", &data).map(|(v, _)| v),
+            Ok(Value::Number(6.0))
+        );
+        assert!(!is_valid_name("RC"));
+        assert!(!is_valid_name("r2c3"));
+    }
+
+    #[test]
+    fn test_names() {
+        let mut data = Data::new();
+        data.insert(CellPos::new(1, 1), "3".to_string());
+        data.insert(CellPos::new(2, 1), "5".to_string());
+        data.define_name("price", "B2").unwrap();
+        data.insert(CellPos::new(0, 0), "=price*2".to_string());
+        assert_eq!(data.get(CellPos::new(0, 0)), Some(&Value::Number(6.0)));
+
+        data.insert(CellPos::new(1, 1), "4".to_string());
+        assert_eq!(data.get(CellPos::new(0, 0)), Some(&Value::Number(8.0)));
+
+        data.define_name("PRICE", "C2").unwrap();
+        assert_eq!(data.get(CellPos::new(0, 0)), Some(&Value::Number(10.0)));
+
+        data.define_name("cost", "B2:C2").unwrap();
+        let names: Vec<_> = data
+            .names()
+            .iter()
+            .map(|(name, target)| format!("{} {}", name, target))
+            .collect();
+        assert_eq!(names, vec!["COST B2:C2", "PRICE C2"]);
+
+        assert!(data.delete_name("price"));
+        assert!(!data.delete_name("price"));
+        assert_eq!(
+            data.get(CellPos::new(0, 0)),
+            Some(&Value::Error(ErrorKind::Name.into()))
+        );
+
+        assert_eq!(data.define_name("B2", "C2"), Err(ErrorKind::Name.into()));
+        assert_eq!(data.define_name("x", "C2:"), Err(ErrorKind::Parse.into()));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut data = Data::new();
+        data.insert(CellPos::new(0, 0), "=1/0".to_string());
+        data.insert(CellPos::new(1, 0), "=A1*2".to_string());
+        match data.get(CellPos::new(1, 0)) {
+            Some(Value::Error(e)) => {
+                assert_eq!(e.to_string(), "#DIV/0!");
+                assert_eq!(e.reason, "Division by zero");
+            }
+            val => panic!("Expected an error, got {:?}", val),
+        }
+
+        data.insert(CellPos::new(2, 0), "=D1".to_string());
+        data.insert(CellPos::new(4, 0), "=C1".to_string());
+        data.insert(CellPos::new(3, 0), "=C1+1".to_string());
+        for x in 2..5 {
+            match data.get(CellPos::new(x, 0)) {
+                Some(Value::Error(e)) => {
+                    assert_eq!(e.to_string(), "#CYCLE!");
+                    assert_eq!(e.reason, "D1 → C1 → D1");
                 }
+                val => panic!("Expected a cycle, got {:?}", val),
             }
         }
+
+        data.insert(CellPos::new(3, 0), "5".to_string());
+        assert_eq!(data.get(CellPos::new(4, 0)), Some(&Value::Number(5.0)));
+
+        // Cells before a cycle are still recalculated
+        let mut data = Data::new();
+        data.insert(CellPos::new(0, 0), "1".to_string());
+        data.insert(CellPos::new(1, 0), "=A1+1".to_string());
+        data.insert(CellPos::new(2, 0), "=B1+D1".to_string());
+        data.insert(CellPos::new(3, 0), "=C1".to_string());
+        data.insert(CellPos::new(0, 0), "5".to_string());
+        assert_eq!(data.get(CellPos::new(1, 0)), Some(&Value::Number(6.0)));
+        match data.get(CellPos::new(3, 0)) {
+            Some(Value::Error(e)) => assert_eq!(e.reason, "C1 → D1 → C1"),
+            val => panic!("Expected a cycle, got {:?}", val),
+        }
+    }
+
+    #[test]
+    fn test_recover_from_errors() {
+        let mut data = Data::new();
+        // B1 is never reached while A1 divides by zero
+        data.insert(CellPos::new(2, 0), "=1/A1 + B1".to_string());
+        assert_eq!(
+            data.get(CellPos::new(2, 0)),
+            Some(&Value::Error(ErrorKind::Div.into()))
+        );
+
+        data.insert(CellPos::new(1, 0), "x".to_string());
+        data.insert(CellPos::new(0, 0), "2".to_string());
+        assert_eq!(
+            data.get(CellPos::new(2, 0)),
+            Some(&Value::Error(ErrorKind::Value.into()))
+        );
+
+        data.insert(CellPos::new(1, 0), "3".to_string());
+        assert_eq!(data.get(CellPos::new(2, 0)), Some(&Value::Number(3.5)));
+    }
+
+    #[test]
+    fn test_sheets() {
+        let mut data = Data::new();
+        let sheet2 = data.add_sheet("Sheet2").unwrap();
+        data.insert_in(sheet2, CellPos::new(0, 0), "5".to_string());
+        data.insert(CellPos::new(1, 0), "=Sheet2!A1*2".to_string());
+        data.insert(CellPos::new(1, 1), "=SUM('My Sheet'!A1:A2)".to_string());
+        assert_eq!(data.get(CellPos::new(1, 0)), Some(&Value::Number(10.0)));
+        assert_eq!(
+            data.get(CellPos::new(1, 1)),
+            Some(&Value::Error(ErrorKind::Ref.into()))
+        );
+
+        data.insert_in(sheet2, CellPos::new(0, 0), "7".to_string());
+        assert_eq!(data.get(CellPos::new(1, 0)), Some(&Value::Number(14.0)));
+
+        let mine = data.add_sheet("my sheet").unwrap();
+        data.insert_in(mine, CellPos::new(0, 1), "3".to_string());
+        assert_eq!(data.get(CellPos::new(1, 1)), Some(&Value::Number(3.0)));
+        assert!(data.add_sheet("MY SHEET").is_err());
+
+        data.rename_sheet(sheet2, "Data 2").unwrap();
+        assert_eq!(
+            data.get_source(CellPos::new(1, 0)),
+            Some("='Data 2'!A1*2".to_string())
+        );
+        data.insert_in(sheet2, CellPos::new(0, 0), "1".to_string());
+        assert_eq!(data.get(CellPos::new(1, 0)), Some(&Value::Number(2.0)));
+
+        data.define_name("total", "'Data 2'!A1").unwrap();
+        data.insert(CellPos::new(1, 2), "=total+1".to_string());
+        assert_eq!(data.get(CellPos::new(1, 2)), Some(&Value::Number(2.0)));
+
+        data.delete_sheet(sheet2).unwrap();
+        assert_eq!(
+            data.get_source(CellPos::new(1, 0)),
+            Some("=#REF!*2".to_string())
+        );
+        assert_eq!(
+            data.get(CellPos::new(1, 0)),
+            Some(&Value::Error(ErrorKind::Ref.into()))
+        );
+        assert_eq!(
+            data.get(CellPos::new(1, 2)),
+            Some(&Value::Error(ErrorKind::Ref.into()))
+        );
+    }
+
+    #[test]
+    fn test_spill() {
+        let mut data = Data::new();
+        data.insert(CellPos::new(3, 1), "=A3*10".to_string());
+        data.insert(CellPos::new(0, 0), "=SEQUENCE(3, 2)".to_string());
+        data.insert(CellPos::new(3, 0), "=SUM(A1#)".to_string());
+        assert_eq!(data.get(CellPos::new(0, 0)), Some(&Value::Number(1.0)));
+        assert_eq!(data.get(CellPos::new(1, 2)), Some(&Value::Number(6.0)));
+        assert_eq!(data.get_source(CellPos::new(1, 2)), None);
+        assert_eq!(data.get(CellPos::new(3, 0)), Some(&Value::Number(21.0)));
+        assert_eq!(data.get(CellPos::new(3, 1)), Some(&Value::Number(50.0)));
+
+        // Typing into the spill range blocks it until the cell is cleared
+        data.insert(CellPos::new(1, 1), "x".to_string());
+        assert_eq!(
+            data.get(CellPos::new(0, 0)),
+            Some(&Value::Error(ErrorKind::Spill.into()))
+        );
+        assert_eq!(data.get(CellPos::new(0, 2)), None);
+        assert_eq!(
+            data.get(CellPos::new(3, 0)),
+            Some(&Value::Error(ErrorKind::Ref.into()))
+        );
+        assert_eq!(data.get(CellPos::new(3, 1)), Some(&Value::Number(0.0)));
+
+        data.insert(CellPos::new(1, 1), String::new());
+        assert_eq!(data.get(CellPos::new(1, 1)), Some(&Value::Number(4.0)));
+        assert_eq!(data.get(CellPos::new(3, 1)), Some(&Value::Number(50.0)));
+
+        // A smaller array frees the cells it no longer covers
+        data.insert(CellPos::new(0, 0), "=SEQUENCE(2)".to_string());
+        assert_eq!(data.get(CellPos::new(0, 2)), None);
+        assert_eq!(data.get(CellPos::new(1, 0)), None);
+        assert_eq!(data.get(CellPos::new(3, 0)), Some(&Value::Number(3.0)));
+        assert_eq!(data.get(CellPos::new(3, 1)), Some(&Value::Number(0.0)));
     }
 }
@@ -0,0 +1,220 @@
+use crate::parse::LangError;
+use std::fmt;
+
+/// The digits kept after the decimal point.
+pub const SCALE: u32 = 4;
+const ONE: i128 = 10_000;
+
+/// How a number is rounded to fewer digits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rounding {
+    /// Halves round away from zero, as ROUND does.
+    HalfUp,
+    /// Halves round to the even neighbour, also known as banker's rounding.
+    HalfEven,
+    /// Away from zero.
+    Up,
+    /// Towards zero.
+    Down,
+}
+
+impl Rounding {
+    /// Divides `n` by `d`, rounding the quotient to a whole number.
+    fn divide(self, n: i128, d: i128) -> i128 {
+        let (q, r) = (n / d, n % d);
+        if r == 0 {
+            return q;
+        }
+
+        let away = q + if (n < 0) != (d < 0) { -1 } else { 1 };
+        let twice = (r.abs() * 2).cmp(&d.abs());
+        match self {
+            Rounding::Up => away,
+            Rounding::Down => q,
+            Rounding::HalfUp if twice.is_lt() => q,
+            Rounding::HalfUp => away,
+            Rounding::HalfEven if twice.is_lt() || (twice.is_eq() && q % 2 == 0) => q,
+            Rounding::HalfEven => away,
+        }
+    }
+}
+
+/// An exact decimal number with four digits after the point, used for
+/// money so that sums such as `$0.10 + $0.20` come out exact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Decimal(i128);
+
+impl Decimal {
+    /// Reads a number such as `-1,234.50`, with at most four decimals.
+    pub fn parse(input: &str) -> Option<Self> {
+        let (negative, digits) = match input.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, input),
+        };
+        let (whole, fraction) = match digits.find('.') {
+            Some(i) => (&digits[..i], &digits[i + 1..]),
+            None => (digits, ""),
+        };
+
+        let groups: Vec<_> = whole.split(',').collect();
+        let grouped = groups.len() > 1;
+        let valid_group = |(i, g): (usize, &&str)| {
+            !g.is_empty()
+                && g.bytes().all(|b| b.is_ascii_digit())
+                && (!grouped || (i == 0 && g.len() <= 3) || g.len() == 3)
+        };
+        if !groups.iter().enumerate().all(valid_group)
+            || fraction.len() > SCALE as usize
+            || !fraction.bytes().all(|b| b.is_ascii_digit())
+        {
+            return None;
+        }
+
+        let mut units: i128 = 0;
+        for b in groups.concat().bytes().chain(fraction.bytes()) {
+            units = units.checked_mul(10)?.checked_add((b - b'0') as i128)?;
+        }
+        units = units.checked_mul(10i128.pow(SCALE - fraction.len() as u32))?;
+        Some(Decimal(if negative { -units } else { units }))
+    }
+
+    /// Reads money such as `$12.50`, `-€3` or `£1,200.99`.
+    pub fn parse_currency(input: &str) -> Option<Self> {
+        let (negative, rest) = match input.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, input),
+        };
+        let rest = rest.strip_prefix(|c| matches!(c, '$' | '€' | '£'))?;
+        let n = Decimal::parse(rest.trim_start())?;
+        if negative {
+            n.checked_neg().ok()
+        } else {
+            Some(n)
+        }
+    }
+
+    /// The nearest decimal to `n`, if it is in range.
+    pub fn from_f64(n: f64) -> Result<Self, LangError> {
+        let units = (n * ONE as f64).round();
+        if units.is_finite() && units.abs() < 1e36 {
+            Ok(Decimal(units as i128))
+        } else {
+            Err(LangError::num("The number is too large for a decimal"))
+        }
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / ONE as f64
+    }
+
+    pub fn checked_add(self, other: Decimal) -> Result<Self, LangError> {
+        self.0
+            .checked_add(other.0)
+            .map(Decimal)
+            .ok_or_else(overflow)
+    }
+
+    pub fn checked_sub(self, other: Decimal) -> Result<Self, LangError> {
+        self.0
+            .checked_sub(other.0)
+            .map(Decimal)
+            .ok_or_else(overflow)
+    }
+
+    /// Multiplies, rounding any digits past the fourth to even.
+    pub fn checked_mul(self, other: Decimal) -> Result<Self, LangError> {
+        let units = self.0.checked_mul(other.0).ok_or_else(overflow)?;
+        Ok(Decimal(Rounding::HalfEven.divide(units, ONE)))
+    }
+
+    /// Divides, rounding any digits past the fourth to even.
+    pub fn checked_div(self, other: Decimal) -> Result<Self, LangError> {
+        if other.0 == 0 {
+            return Err(LangError::div("Division by zero"));
+        }
+        let units = self.0.checked_mul(ONE).ok_or_else(overflow)?;
+        Ok(Decimal(Rounding::HalfEven.divide(units, other.0)))
+    }
+
+    pub fn checked_neg(self) -> Result<Self, LangError> {
+        self.0.checked_neg().map(Decimal).ok_or_else(overflow)
+    }
+
+    /// Rounds to `digits` after the point, or before it when negative.
+    pub fn round(self, digits: i32, rounding: Rounding) -> Result<Self, LangError> {
+        if digits >= SCALE as i32 {
+            return Ok(self);
+        }
+        let step = 10i128
+            .checked_pow((SCALE as i32 - digits) as u32)
+            .ok_or_else(overflow)?;
+        let steps = rounding.divide(self.0, step);
+        steps.checked_mul(step).map(Decimal).ok_or_else(overflow)
+    }
+}
+
+impl From<i64> for Decimal {
+    fn from(n: i64) -> Self {
+        Decimal(n as i128 * ONE)
+    }
+}
+
+fn overflow() -> LangError {
+    LangError::num("The decimal is too large")
+}
+
+/// Shows at least two decimals and groups thousands: `1,234.50`.
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let units = self.0.unsigned_abs();
+        let whole = (units / ONE as u128).to_string();
+        let fraction = format!("{:04}", units % ONE as u128);
+        let fraction = fraction.trim_end_matches('0');
+
+        let mut grouped = String::new();
+        for (i, c) in whole.chars().enumerate() {
+            if i > 0 && (whole.len() - i).is_multiple_of(3) {
+                grouped.push(',');
+            }
+            grouped.push(c);
+        }
+        let sign = if self.0 < 0 { "-" } else { "" };
+        write!(f, "{}{}.{:0<2}", sign, grouped, fraction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decimal() {
+        let d = |s: &str| Decimal::parse(s).unwrap();
+        assert_eq!(d("0.1").checked_add(d("0.2")), Ok(d("0.3")));
+        assert_eq!(d("1,234.5").to_string(), "1,234.50");
+        assert_eq!(d("-0.125").to_string(), "-0.125");
+        assert_eq!(d("1000000").to_string(), "1,000,000.00");
+        assert_eq!(Decimal::parse("1,23"), None);
+        assert_eq!(Decimal::parse("1.23456"), None);
+        assert_eq!(Decimal::parse_currency("-$1,000.10"), Some(d("-1000.1")));
+        assert_eq!(Decimal::parse_currency("€-3"), Some(d("-3")));
+        assert_eq!(Decimal::parse_currency("12.50"), None);
+
+        assert_eq!(d("10").checked_div(d("3")), Ok(d("3.3333")));
+        assert_eq!(d("0.0001").checked_mul(d("0.5")), Ok(d("0")));
+        assert_eq!(
+            d("1").checked_div(d("0")),
+            Err(crate::parse::ErrorKind::Div.into())
+        );
+
+        let round = |s: &str, digits, rounding| d(s).round(digits, rounding).unwrap().to_string();
+        assert_eq!(round("2.675", 2, Rounding::HalfUp), "2.68");
+        assert_eq!(round("2.665", 2, Rounding::HalfEven), "2.66");
+        assert_eq!(round("2.675", 2, Rounding::HalfEven), "2.68");
+        assert_eq!(round("-2.5", 0, Rounding::HalfUp), "-3.00");
+        assert_eq!(round("-2.5", 0, Rounding::HalfEven), "-2.00");
+        assert_eq!(round("2.001", 2, Rounding::Up), "2.01");
+        assert_eq!(round("-2.009", 2, Rounding::Down), "-2.00");
+        assert_eq!(round("1,250", -2, Rounding::HalfEven), "1,200.00");
+    }
+}
@@ -0,0 +1,403 @@
+use crate::ast::{compile_pairs, Expr};
+use crate::parse::{
+    convert_pair_to_sheet_name, parse_error, quote_sheet_name, LangError, LangParser, Rule,
+};
+use crate::position::{column_index, column_name, row_index, row_number, CellPos, MAX_COLUMNS};
+use pest::iterators::Pair;
+use pest::Parser;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+/// One half of a cell reference. A `$` makes it absolute, otherwise it is
+/// stored as an offset from the cell holding the formula.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Coord {
+    Absolute(usize),
+    Relative(i32),
+}
+
+impl Coord {
+    fn resolve(self, origin: usize) -> Option<usize> {
+        match self {
+            Coord::Absolute(n) => Some(n),
+            Coord::Relative(offset) => {
+                let n = origin as i64 + offset as i64;
+                if n < 0 {
+                    None
+                } else {
+                    Some(n as usize)
+                }
+            }
+        }
+    }
+
+    fn marker(self) -> &'static str {
+        match self {
+            Coord::Absolute(_) => "$",
+            Coord::Relative(_) => "",
+        }
+    }
+
+    /// Reads the row or column of an R1C1 reference, which is absolute
+    /// when given as a number and relative otherwise. Numbers too large to
+    /// count give `None`.
+    fn from_r1c1(pair: Pair<Rule>) -> Option<Self> {
+        match pair.into_inner().next() {
+            Some(p) if p.as_rule() == Rule::r1c1_index => {
+                row_index(p.as_str()).map(Coord::Absolute)
+            }
+            Some(p) => p.as_str().parse().ok().map(Coord::Relative),
+            None => Some(Coord::Relative(0)),
+        }
+    }
+
+    /// Renders the row or column after its `R` or `C`.
+    fn to_r1c1(self) -> String {
+        match self {
+            Coord::Absolute(n) => row_number(n).to_string(),
+            Coord::Relative(0) => String::new(),
+            Coord::Relative(offset) => format!("[{}]", offset),
+        }
+    }
+}
+
+/// How formulas are shown: `B2` or `R[1]C[1]` for the cell below and to
+/// the right.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Notation {
+    #[default]
+    A1,
+    R1C1,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reference {
+    pub col: Coord,
+    pub row: Coord,
+}
+
+impl Reference {
+    /// Reads a `cell` or `r1c1` pair of a formula entered at `origin`.
+    /// Rows too large to count give `#REF!`.
+    pub fn from_pair(pair: Pair<Rule>, origin: CellPos) -> Result<Self, LangError> {
+        let source = pair.as_str();
+        let off_sheet = || LangError::reference(format!("{} is not on the sheet", source));
+        if pair.as_rule() == Rule::r1c1 {
+            let mut inner = pair.into_inner();
+            let row = Coord::from_r1c1(inner.next().unwrap()).ok_or_else(off_sheet)?;
+            let col = Coord::from_r1c1(inner.next().unwrap()).ok_or_else(off_sheet)?;
+            return Ok(Self { col, row });
+        }
+
+        let (mut col_abs, mut row_abs) = (false, false);
+        let (mut col, mut row) = (0, 0);
+        for p in pair.into_inner() {
+            match p.as_rule() {
+                Rule::col_abs => col_abs = true,
+                Rule::row_abs => row_abs = true,
+                // A column past the last only fails once the formula is
+                // evaluated, so it is kept as the first column off the sheet.
+                Rule::cell_alpha => col = column_index(p.as_str()).unwrap_or(MAX_COLUMNS),
+                _ => row = row_index(p.as_str()).ok_or_else(off_sheet)?,
+            }
+        }
+
+        let offset = |n: usize, from: usize| {
+            let n = i64::try_from(n).ok()? - from as i64;
+            i32::try_from(n).ok()
+        };
+        Ok(Self {
+            col: if col_abs {
+                Coord::Absolute(col)
+            } else {
+                Coord::Relative(offset(col, origin.x).ok_or_else(off_sheet)?)
+            },
+            row: if row_abs {
+                Coord::Absolute(row)
+            } else {
+                Coord::Relative(offset(row, origin.y).ok_or_else(off_sheet)?)
+            },
+        })
+    }
+
+    /// Whether the reference points at the same cell from anywhere.
+    pub fn is_absolute(&self) -> bool {
+        matches!(
+            (self.col, self.row),
+            (Coord::Absolute(_), Coord::Absolute(_))
+        )
+    }
+
+    /// The cell referred to from `origin`, if it is still on the sheet.
+    pub fn resolve(&self, origin: CellPos) -> Option<CellPos> {
+        Some(CellPos::new(
+            self.col.resolve(origin.x)?,
+            self.row.resolve(origin.y)?,
+        ))
+    }
+
+    fn render(self, origin: CellPos, notation: Notation) -> Option<String> {
+        match notation {
+            Notation::A1 => self.to_a1(origin),
+            Notation::R1C1 => Some(format!("R{}C{}", self.row.to_r1c1(), self.col.to_r1c1())),
+        }
+    }
+
+    fn to_a1(self, origin: CellPos) -> Option<String> {
+        let pos = self.resolve(origin)?;
+        Some(format!(
+            "{}{}{}{}",
+            self.col.marker(),
+            column_name(pos.x),
+            self.row.marker(),
+            row_number(pos.y)
+        ))
+    }
+}
+
+/// A piece of a formula. References carry the name of their sheet when
+/// one was given.
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Cell(Option<String>, Reference),
+    Range(Option<String>, Reference, Reference),
+}
+
+/// A formula stored independently of the cell holding it, so the same
+/// formula can be rendered for any other cell it is copied to. It is
+/// compiled once for the cell it was entered in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Formula {
+    parts: Vec<Part>,
+    expr: Result<Rc<Expr>, LangError>,
+}
+
+impl Formula {
+    /// Splits `source`, entered at `origin`, into text and references.
+    /// Source that does not parse is kept as it is.
+    pub fn new(source: &str, origin: CellPos) -> Self {
+        let pairs = match LangParser::parse(Rule::ident, source) {
+            Ok(pairs) => pairs,
+            Err(e) => {
+                return Self {
+                    parts: vec![Part::Text(source.to_string())],
+                    expr: Err(parse_error(e)),
+                }
+            }
+        };
+
+        let expr = compile_pairs(pairs.clone(), Some(origin)).map(Rc::new);
+        let mut refs = Vec::new();
+        for pair in pairs {
+            if let Err(e) = collect_references(pair, origin, None, &mut refs) {
+                return Self {
+                    parts: vec![Part::Text(source.to_string())],
+                    expr: Err(e),
+                };
+            }
+        }
+
+        let mut parts = Vec::new();
+        let mut last = 0;
+        for (start, end, part) in refs {
+            if start > last {
+                parts.push(Part::Text(source[last..start].to_string()));
+            }
+            parts.push(part);
+            last = end;
+        }
+        if last < source.len() {
+            parts.push(Part::Text(source[last..].to_string()));
+        }
+        Self { parts, expr }
+    }
+
+    /// The compiled formula, or why it could not be compiled.
+    pub fn expr(&self) -> Result<Rc<Expr>, LangError> {
+        self.expr.clone()
+    }
+
+    /// Renders the formula in A1 notation as seen from `origin`. References
+    /// that would fall off the sheet become `#REF!`.
+    pub fn to_source(&self, origin: CellPos) -> String {
+        self.to_source_in(origin, Notation::A1)
+    }
+
+    /// Renders the formula as seen from `origin` in either notation.
+    pub fn to_source_in(&self, origin: CellPos, notation: Notation) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            let text = match part {
+                Part::Text(text) => Some(text.clone()),
+                Part::Cell(sheet, r) => r.render(origin, notation).map(|r| with_sheet(sheet, r)),
+                Part::Range(sheet, a, b) => a
+                    .render(origin, notation)
+                    .and_then(|a| Some(format!("{}:{}", a, b.render(origin, notation)?)))
+                    .map(|r| with_sheet(sheet, r)),
+            };
+            out.push_str(text.as_deref().unwrap_or("#REF!"));
+        }
+        out
+    }
+
+    /// Whether any reference names the sheet `name`.
+    pub fn refers_to_sheet(&self, name: &str) -> bool {
+        self.parts.iter().any(|part| match part {
+            Part::Cell(Some(sheet), _) | Part::Range(Some(sheet), _, _) => {
+                sheet.eq_ignore_ascii_case(name)
+            }
+            _ => false,
+        })
+    }
+
+    /// Points references to the sheet `old` at `new` instead.
+    pub fn rename_sheet(&mut self, old: &str, new: &str) {
+        for part in &mut self.parts {
+            if let Part::Cell(Some(sheet), _) | Part::Range(Some(sheet), _, _) = part {
+                if sheet.eq_ignore_ascii_case(old) {
+                    *sheet = new.to_string();
+                }
+            }
+        }
+    }
+
+    /// Turns references to the sheet `name` into `#REF!`, returning whether
+    /// there were any.
+    pub fn remove_sheet(&mut self, name: &str) -> bool {
+        let found = self.refers_to_sheet(name);
+        for part in &mut self.parts {
+            if let Part::Cell(Some(sheet), _) | Part::Range(Some(sheet), _, _) = part {
+                if sheet.eq_ignore_ascii_case(name) {
+                    *part = Part::Text("#REF!".to_string());
+                }
+            }
+        }
+        found
+    }
+}
+
+fn with_sheet(sheet: &Option<String>, reference: String) -> String {
+    match sheet {
+        Some(sheet) => format!("{}!{}", quote_sheet_name(sheet), reference),
+        None => reference,
+    }
+}
+
+/// Collects the references below `pair`. Inside a `Sheet2!` prefix,
+/// `sheet` holds the name and where the prefix starts.
+fn collect_references(
+    pair: Pair<Rule>,
+    origin: CellPos,
+    sheet: Option<(usize, String)>,
+    refs: &mut Vec<(usize, usize, Part)>,
+) -> Result<(), LangError> {
+    let span = pair.as_span();
+    let start = sheet.as_ref().map_or(span.start(), |(start, _)| *start);
+    let name = sheet.as_ref().map(|(_, name)| name.clone());
+    match pair.as_rule() {
+        Rule::cell | Rule::r1c1 => {
+            let r = Reference::from_pair(pair, origin)?;
+            refs.push((start, span.end(), Part::Cell(name, r)));
+        }
+        Rule::range => {
+            let mut inner = pair.into_inner();
+            let a = Reference::from_pair(inner.next().unwrap(), origin)?;
+            let b = Reference::from_pair(inner.next().unwrap(), origin)?;
+            refs.push((start, span.end(), Part::Range(name, a, b)));
+        }
+        Rule::sheet_ref => {
+            let mut inner = pair.into_inner();
+            let name = convert_pair_to_sheet_name(inner.next().unwrap());
+            let sheet = Some((span.start(), name));
+            inner.try_for_each(|p| collect_references(p, origin, sheet.clone(), refs))?;
+        }
+        _ => pair
+            .into_inner()
+            .try_for_each(|p| collect_references(p, origin, sheet.clone(), refs))?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::ErrorKind;
+
+    #[test]
+    fn test_copy_formula() {
+        let formula = Formula::new("=A1+$B1*B$1-$C$2+SUM(A1:$A$3)", CellPos::new(3, 3));
+        assert_eq!(
+            formula.to_source(CellPos::new(3, 3)),
+            "=A1+$B1*B$1-$C$2+SUM(A1:$A$3)"
+        );
+        assert_eq!(
+            formula.to_source(CellPos::new(5, 5)),
+            "=C3+$B3*D$1-$C$2+SUM(C3:$A$3)"
+        );
+    }
+
+    #[test]
+    fn test_copy_formula_off_sheet() {
+        let formula = Formula::new("=B2+SUM(A1:A2)&\"B2\"", CellPos::new(2, 1));
+        assert_eq!(
+            formula.to_source(CellPos::new(1, 0)),
+            "=A1+SUM(#REF!)&\"B2\""
+        );
+    }
+
+    #[test]
+    fn test_sheet_references() {
+        let mut formula = Formula::new("=Sheet2!A1+'My Sheet'!$B$1:B2+A1", CellPos::new(0, 0));
+        assert_eq!(
+            formula.to_source(CellPos::new(1, 1)),
+            "=Sheet2!B2+'My Sheet'!$B$1:C3+B2"
+        );
+        assert!(formula.refers_to_sheet("my sheet"));
+
+        formula.rename_sheet("sheet2", "Totals 2020");
+        assert_eq!(
+            formula.to_source(CellPos::new(0, 0)),
+            "='Totals 2020'!A1+'My Sheet'!$B$1:B2+A1"
+        );
+
+        assert!(formula.remove_sheet("My Sheet"));
+        assert!(!formula.remove_sheet("My Sheet"));
+        assert_eq!(
+            formula.to_source(CellPos::new(0, 0)),
+            "='Totals 2020'!A1+#REF!+A1"
+        );
+    }
+
+    #[test]
+    fn test_r1c1() {
+        let formula = Formula::new("=A1+$B1*B$1-$C$2+SUM(A1:$A$3)", CellPos::new(3, 3));
+        assert_eq!(
+            formula.to_source_in(CellPos::new(3, 3), Notation::R1C1),
+            "=R[-3]C[-3]+R[-3]C2*R1C[-2]-R2C3+SUM(R[-3]C[-3]:R3C1)"
+        );
+
+        let formula = Formula::new("=R[-1]C+rc[2]*R1C1", CellPos::new(1, 1));
+        assert_eq!(formula.to_source(CellPos::new(1, 1)), "=B1+D2*$A$1");
+        assert_eq!(
+            formula.to_source_in(CellPos::new(2, 5), Notation::R1C1),
+            "=R[-1]C+RC[2]*R1C1"
+        );
+        assert_eq!(formula.to_source(CellPos::new(0, 0)), "=#REF!+C1*$A$1");
+    }
+
+    #[test]
+    fn test_rows_off_sheet() {
+        for source in ["=A99999999999999999999999+1", "=SUM(A1:A3000000000)"].iter() {
+            let formula = Formula::new(source, CellPos::new(0, 0));
+            assert_eq!(formula.expr(), Err(ErrorKind::Ref.into()), "{}", source);
+            assert_eq!(formula.to_source(CellPos::new(1, 1)), *source);
+        }
+    }
+
+    #[test]
+    fn test_unparsable_formula() {
+        let formula = Formula::new("=add (A1", CellPos::new(0, 0));
+        assert_eq!(formula.to_source(CellPos::new(4, 3)), "=add (A1");
+    }
+}
@@ -0,0 +1,162 @@
+use super::{decimals, numbers, quantities, Arity, Registry};
+use crate::decimal::Decimal;
+use crate::parse::LangError;
+use crate::value::Value;
+
+pub fn register(registry: &mut Registry) {
+    registry.builtin("SUM", Arity::at_least(1), &[], sum);
+    registry.builtin("AVERAGE", Arity::at_least(1), &[], average);
+    registry.builtin("MIN", Arity::at_least(1), &[], min);
+    registry.builtin("MAX", Arity::at_least(1), &[], max);
+    registry.builtin("COUNT", Arity::at_least(1), &[], count);
+    registry.builtin("COUNTA", Arity::at_least(1), &[], counta);
+}
+
+/// Applies `f` to the numbers in `args`. Quantities are converted to the
+/// unit of the first one, which the result keeps.
+fn aggregate(
+    args: &[Value],
+    f: impl Fn(Vec<f64>) -> Result<f64, LangError>,
+) -> Result<Value, LangError> {
+    match quantities(args) {
+        Some(vals) => {
+            let (vals, unit) = vals?;
+            Ok(Value::Quantity(f(vals)?, unit))
+        }
+        None => Ok(Value::Number(f(numbers(args)?)?)),
+    }
+}
+
+/// Money is summed exactly.
+pub fn sum(args: &[Value]) -> Result<Value, LangError> {
+    if let Some(vals) = decimals(args) {
+        let total = vals?
+            .into_iter()
+            .try_fold(Decimal::default(), Decimal::checked_add)?;
+        return Ok(Value::Decimal(total));
+    }
+    aggregate(args, |vals| Ok(vals.iter().sum()))
+}
+
+pub fn average(args: &[Value]) -> Result<Value, LangError> {
+    aggregate(args, |vals| {
+        if vals.is_empty() {
+            return Err(LangError::div("There are no numbers to average"));
+        }
+        Ok(vals.iter().sum::<f64>() / vals.len() as f64)
+    })
+}
+
+pub fn min(args: &[Value]) -> Result<Value, LangError> {
+    aggregate(args, |vals| {
+        Ok(vals
+            .into_iter()
+            .fold(None, |acc: Option<f64>, n| {
+                Some(acc.map_or(n, |m| m.min(n)))
+            })
+            .unwrap_or(0.0))
+    })
+}
+
+pub fn max(args: &[Value]) -> Result<Value, LangError> {
+    aggregate(args, |vals| {
+        Ok(vals
+            .into_iter()
+            .fold(None, |acc: Option<f64>, n| {
+                Some(acc.map_or(n, |m| m.max(n)))
+            })
+            .unwrap_or(0.0))
+    })
+}
+
+/// Counts the numeric values, never failing on text or errors.
+pub fn count(args: &[Value]) -> Result<Value, LangError> {
+    let n = args
+        .iter()
+        .map(|arg| match arg {
+            Value::Array(_) => arg.iter().filter(|v| v.is_numeric()).count(),
+            Value::Empty => 0,
+            v if v.is_numeric() => 1,
+            v => v.as_number().map_or(0, |_| 1),
+        })
+        .sum::<usize>();
+    Ok(Value::Number(n as f64))
+}
+
+/// Counts every value that is not blank.
+pub fn counta(args: &[Value]) -> Result<Value, LangError> {
+    let n = args
+        .iter()
+        .flat_map(|arg| arg.iter())
+        .filter(|v| !v.is_empty())
+        .count();
+    Ok(Value::Number(n as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::ErrorKind;
+    use crate::unit::Unit;
+
+    fn column(vals: &[Value]) -> Value {
+        Value::Array(vals.iter().map(|v| vec![v.clone()]).collect())
+    }
+
+    #[test]
+    fn test_aggregates() {
+        let range = column(&[
+            Value::Number(1.0),
+            Value::Empty,
+            Value::Text("x".to_string()),
+            Value::Number(5.0),
+            Value::Bool(true),
+        ]);
+        let args = [range, Value::Number(3.0)];
+
+        assert_eq!(sum(&args), Ok(Value::Number(9.0)));
+        assert_eq!(average(&args), Ok(Value::Number(3.0)));
+        assert_eq!(min(&args), Ok(Value::Number(1.0)));
+        assert_eq!(max(&args), Ok(Value::Number(5.0)));
+        assert_eq!(count(&args), Ok(Value::Number(3.0)));
+        assert_eq!(counta(&args), Ok(Value::Number(5.0)));
+    }
+
+    #[test]
+    fn test_aggregates_empty() {
+        let args = [column(&[Value::Empty, Value::Text("x".to_string())])];
+        assert_eq!(sum(&args), Ok(Value::Number(0.0)));
+        assert_eq!(max(&args), Ok(Value::Number(0.0)));
+        assert_eq!(average(&args), Err(ErrorKind::Div.into()));
+    }
+
+    #[test]
+    fn test_aggregates_errors() {
+        let args = [column(&[
+            Value::Number(1.0),
+            Value::Error(ErrorKind::Div.into()),
+        ])];
+        assert_eq!(sum(&args), Err(ErrorKind::Div.into()));
+        assert_eq!(count(&args), Ok(Value::Number(1.0)));
+
+        let args = [Value::Text("x".to_string())];
+        assert_eq!(sum(&args), Err(ErrorKind::Value.into()));
+    }
+
+    #[test]
+    fn test_aggregates_quantities() {
+        let quantity = |n, symbol| Value::Quantity(n, Unit::find(symbol).unwrap());
+        let args = [
+            column(&[quantity(1.0, "m"), Value::Empty, quantity(50.0, "cm")]),
+            quantity(2.5, "m"),
+        ];
+        assert_eq!(sum(&args), Ok(quantity(4.0, "m")));
+        assert_eq!(min(&args), Ok(quantity(0.5, "m")));
+        assert_eq!(count(&args), Ok(Value::Number(3.0)));
+
+        let args = [quantity(1.0, "m"), Value::Number(1.0)];
+        assert_eq!(sum(&args), Err(ErrorKind::Value.into()));
+        let args = [quantity(1.0, "m"), quantity(1.0, "kg")];
+        assert_eq!(max(&args), Err(ErrorKind::Value.into()));
+    }
+}
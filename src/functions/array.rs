@@ -0,0 +1,237 @@
+use super::{optional, rows, Arity, Registry};
+use crate::parse::LangError;
+use crate::position::MAX_CELLS;
+use crate::value::Value;
+use std::cmp::Ordering;
+
+pub fn register(registry: &mut Registry) {
+    registry.builtin("SEQUENCE", Arity::range(1, 4), &[], sequence);
+    registry.builtin("SORT", Arity::range(1, 4), &[], sort);
+    registry.builtin("FILTER", Arity::range(2, 3), &[], filter);
+    registry.builtin("UNIQUE", Arity::range(1, 3), &[], unique);
+}
+
+/// `SEQUENCE(rows, [columns], [start], [step])` counts across each row and
+/// then down.
+pub fn sequence(args: &[Value]) -> Result<Value, LangError> {
+    let height = optional(args, 0, 1.0)?.trunc();
+    let width = optional(args, 1, 1.0)?.trunc();
+    let start = optional(args, 2, 1.0)?;
+    let step = optional(args, 3, 1.0)?;
+    if height < 1.0 || width < 1.0 || height * width > MAX_CELLS as f64 {
+        return Err(LangError::num("The size of the sequence is not valid"));
+    }
+
+    let (height, width) = (height as usize, width as usize);
+    let rows = (0..height)
+        .map(|r| {
+            (0..width)
+                .map(|c| Value::Number(start + step * (r * width + c) as f64))
+                .collect()
+        })
+        .collect();
+    Ok(Value::Array(rows))
+}
+
+/// `SORT(array, [index], [order], [by_column])` sorts the rows of an array
+/// by the values in column `index`, or the columns by row `index`. Order 1
+/// is ascending and -1 descending.
+pub fn sort(args: &[Value]) -> Result<Value, LangError> {
+    let by_col = args.get(3).map_or(Ok(false), |v| v.as_bool())?;
+    let mut table = oriented(rows(&args[0]), by_col);
+    let index = optional(args, 1, 1.0)?.trunc();
+    let descending = match optional(args, 2, 1.0)? {
+        1.0 => false,
+        -1.0 => true,
+        _ => return Err(LangError::value("The sort order must be 1 or -1")),
+    };
+
+    let width = table.iter().map(Vec::len).min().unwrap_or(0);
+    if index < 1.0 || index > width as f64 {
+        return Err(LangError::value(format!("There is no column {}", index)));
+    }
+
+    let key = index as usize - 1;
+    table.sort_by(|a, b| {
+        let ord = a[key].compare(&b[key]).unwrap_or(Ordering::Equal);
+        if descending {
+            ord.reverse()
+        } else {
+            ord
+        }
+    });
+    Ok(Value::Array(oriented(table, by_col)))
+}
+
+/// `FILTER(array, include, [if_empty])` keeps the rows of an array for which
+/// `include`, a column of the same height, is true. A row of the same
+/// width filters columns instead.
+pub fn filter(args: &[Value]) -> Result<Value, LangError> {
+    let table = rows(&args[0]);
+    let include = rows(&args[1]);
+    let width = table.first().map_or(0, Vec::len);
+
+    let by_col = if include.len() == table.len() && include.iter().all(|r| r.len() == 1) {
+        false
+    } else if include.len() == 1 && include[0].len() == width {
+        true
+    } else {
+        return Err(LangError::value(
+            "The filter does not match the size of the range",
+        ));
+    };
+
+    let keep = include
+        .into_iter()
+        .flatten()
+        .map(|v| v.as_bool())
+        .collect::<Result<Vec<_>, _>>()?;
+    let kept: Vec<_> = oriented(table, by_col)
+        .into_iter()
+        .zip(keep)
+        .filter(|(_, keep)| *keep)
+        .map(|(row, _)| row)
+        .collect();
+
+    if kept.is_empty() {
+        return match args.get(2) {
+            Some(Value::Empty) | None => Err(LangError::na("Nothing matches the filter")),
+            Some(val) => Ok(val.clone()),
+        };
+    }
+    Ok(Value::Array(oriented(kept, by_col)))
+}
+
+/// `UNIQUE(array, [by_column], [exactly_once])` drops repeated rows, or
+/// columns, keeping the first. With `exactly_once` only rows that are never
+/// repeated are kept.
+pub fn unique(args: &[Value]) -> Result<Value, LangError> {
+    let by_col = args.get(1).map_or(Ok(false), |v| v.as_bool())?;
+    let exactly_once = args.get(2).map_or(Ok(false), |v| v.as_bool())?;
+    let table = oriented(rows(&args[0]), by_col);
+
+    let same = |a: &Vec<Value>, b: &Vec<Value>| {
+        a.len() == b.len()
+            && a.iter()
+                .zip(b)
+                .all(|(x, y)| x.compare(y) == Ok(Ordering::Equal))
+    };
+
+    let mut kept: Vec<Vec<Value>> = Vec::new();
+    for row in &table {
+        let count = table.iter().filter(|other| same(row, other)).count();
+        let seen = kept.iter().any(|other| same(row, other));
+        if !seen && (!exactly_once || count == 1) {
+            kept.push(row.clone());
+        }
+    }
+
+    if kept.is_empty() {
+        return Err(LangError::na("No values are unique"));
+    }
+    Ok(Value::Array(oriented(kept, by_col)))
+}
+
+/// Swaps rows and columns when working column by column.
+fn oriented(table: Vec<Vec<Value>>, by_col: bool) -> Vec<Vec<Value>> {
+    if !by_col {
+        return table;
+    }
+    let width = table.iter().map(Vec::len).max().unwrap_or(0);
+    (0..width)
+        .map(|c| {
+            table
+                .iter()
+                .map(|row| row.get(c).cloned().unwrap_or_default())
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Data;
+    use crate::functions::testing::eval;
+    use crate::parse::ErrorKind;
+    use crate::position::CellPos;
+
+    fn column(vals: &[f64]) -> Value {
+        Value::Array(vals.iter().map(|n| vec![Value::Number(*n)]).collect())
+    }
+
+    #[test]
+    fn test_sequence() {
+        let data = Data::new();
+        assert_eq!(eval("=SEQUENCE(3)", &data), Ok(column(&[1.0, 2.0, 3.0])));
+        assert_eq!(
+            eval("=SEQUENCE(2, 2, 0, 10)", &data),
+            Ok(Value::Array(vec![
+                vec![Value::Number(0.0), Value::Number(10.0)],
+                vec![Value::Number(20.0), Value::Number(30.0)],
+            ]))
+        );
+        assert_eq!(
+            eval("=SUM(SEQUENCE(100))", &data),
+            Ok(Value::Number(5050.0))
+        );
+        assert_eq!(eval("=SEQUENCE(0)", &data), Err(ErrorKind::Num.into()));
+    }
+
+    #[test]
+    fn test_sort_filter_unique() {
+        let mut data = Data::new();
+        for (y, (name, score)) in [("b", "3"), ("a", "5"), ("c", "1"), ("a", "5")]
+            .iter()
+            .enumerate()
+        {
+            data.insert(CellPos::new(0, y), name.to_string());
+            data.insert(CellPos::new(1, y), score.to_string());
+        }
+
+        assert_eq!(
+            eval("=SORT(B1:B4)", &data),
+            Ok(column(&[1.0, 3.0, 5.0, 5.0]))
+        );
+        assert_eq!(
+            eval("=SORT(B1:B4, 1, -1)", &data),
+            Ok(column(&[5.0, 5.0, 3.0, 1.0]))
+        );
+        assert_eq!(
+            eval("=INDEX(SORT(A1:B4, 2), 1, 1)", &data),
+            Ok(Value::Text("c".to_string()))
+        );
+        assert_eq!(eval("=SORT(A1:B4, 3)", &data), Err(ErrorKind::Value.into()));
+
+        assert_eq!(
+            eval("=FILTER(B1:B4, B1:B4>2)", &data),
+            Ok(column(&[3.0, 5.0, 5.0]))
+        );
+        assert_eq!(
+            eval("=COUNTA(FILTER(A1:B4, A1:A4=\"a\"))", &data),
+            Ok(Value::Number(4.0))
+        );
+        assert_eq!(
+            eval("=FILTER(B1:B4, B1:B4>9)", &data),
+            Err(ErrorKind::Na.into())
+        );
+        assert_eq!(
+            eval("=FILTER(B1:B4, B1:B4>9, 0)", &data),
+            Ok(Value::Number(0.0))
+        );
+        assert_eq!(
+            eval("=FILTER(B1:B4, B1:B2>2)", &data),
+            Err(ErrorKind::Value.into())
+        );
+
+        assert_eq!(eval("=UNIQUE(B1:B4)", &data), Ok(column(&[3.0, 5.0, 1.0])));
+        assert_eq!(
+            eval("=COUNTA(UNIQUE(A1:B4))", &data),
+            Ok(Value::Number(6.0))
+        );
+        assert_eq!(
+            eval("=UNIQUE(B1:B4, FALSE, TRUE)", &data),
+            Ok(column(&[3.0, 1.0]))
+        );
+    }
+}
@@ -0,0 +1,305 @@
+use super::{Arity, Coerce::*, Registry};
+use crate::parse::LangError;
+use crate::value::Value;
+use chrono::{Datelike, Duration, Local, NaiveDate, Weekday};
+use std::convert::TryFrom;
+
+pub fn register(registry: &mut Registry) {
+    registry.builtin("TODAY", Arity::exactly(0), &[], today);
+    registry.builtin("NOW", Arity::exactly(0), &[], now);
+    registry.builtin("DATE", Arity::exactly(3), &[Number], date);
+    registry.builtin("YEAR", Arity::exactly(1), &[Date], year);
+    registry.builtin("MONTH", Arity::exactly(1), &[Date], month);
+    registry.builtin("DAY", Arity::exactly(1), &[Date], day);
+    registry.builtin("EDATE", Arity::exactly(2), &[Date, Number], edate);
+    registry.builtin("EOMONTH", Arity::exactly(2), &[Date, Number], eomonth);
+    registry.builtin(
+        "NETWORKDAYS",
+        Arity::range(2, 3),
+        &[Date, Date, Any],
+        networkdays,
+    );
+    registry.builtin("DATEDIF", Arity::exactly(3), &[Date, Date, Text], datedif);
+}
+
+pub fn today(_args: &[Value]) -> Result<Value, LangError> {
+    Ok(Value::Date(Local::today().naive_local()))
+}
+
+pub fn now(_args: &[Value]) -> Result<Value, LangError> {
+    Ok(Value::DateTime(Local::now().naive_local()))
+}
+
+/// `DATE(year, month, day)`, where months and days past the end roll over
+/// into the following year or month.
+pub fn date(args: &[Value]) -> Result<Value, LangError> {
+    let year = args[0].as_number()?.trunc() as i64;
+    let month = args[1].as_number()?.trunc() as i64;
+    let day = args[2].as_number()?.trunc() as i64;
+
+    let index = year
+        .checked_mul(12)
+        .and_then(|n| n.checked_add(month - 1))
+        .ok_or_else(out_of_range)?;
+    first_of_month(index)?
+        .checked_add_signed(days(day - 1)?)
+        .map(Value::Date)
+        .ok_or_else(out_of_range)
+}
+
+pub fn year(args: &[Value]) -> Result<Value, LangError> {
+    Ok(Value::Number(args[0].as_date()?.year() as f64))
+}
+
+pub fn month(args: &[Value]) -> Result<Value, LangError> {
+    Ok(Value::Number(args[0].as_date()?.month() as f64))
+}
+
+pub fn day(args: &[Value]) -> Result<Value, LangError> {
+    Ok(Value::Number(args[0].as_date()?.day() as f64))
+}
+
+/// The same day a number of months later, or the last day of that month
+/// if it is shorter.
+pub fn edate(args: &[Value]) -> Result<Value, LangError> {
+    let months = args[1].as_number()?.trunc() as i64;
+    add_months(args[0].as_date()?, months).map(Value::Date)
+}
+
+/// The last day of the month a number of months later.
+pub fn eomonth(args: &[Value]) -> Result<Value, LangError> {
+    let start = args[0].as_date()?;
+    let months = args[1].as_number()?.trunc() as i64;
+    let index = month_index(start)
+        .checked_add(months)
+        .and_then(|n| n.checked_add(1))
+        .ok_or_else(out_of_range)?;
+    first_of_month(index)?
+        .pred_opt()
+        .map(Value::Date)
+        .ok_or_else(out_of_range)
+}
+
+/// Counts the weekdays from start to end inclusive, leaving out any dates
+/// in the optional holidays. It is negative when end comes first.
+pub fn networkdays(args: &[Value]) -> Result<Value, LangError> {
+    let (start, end) = (args[0].as_date()?, args[1].as_date()?);
+    let (from, to, sign) = if start <= end {
+        (start, end, 1.0)
+    } else {
+        (end, start, -1.0)
+    };
+
+    let is_workday =
+        |date: &NaiveDate| date.weekday() != Weekday::Sat && date.weekday() != Weekday::Sun;
+
+    let days = to.signed_duration_since(from).num_days() + 1;
+    let mut count = days / 7 * 5;
+    let rest = from + Duration::days(days / 7 * 7);
+    count += (0..days % 7)
+        .map(|i| rest + Duration::days(i))
+        .filter(is_workday)
+        .count() as i64;
+
+    let mut holidays = Vec::new();
+    if let Some(arg) = args.get(2) {
+        for val in arg.iter().filter(|v| !v.is_empty()) {
+            let holiday = val.as_date()?;
+            if holiday >= from
+                && holiday <= to
+                && is_workday(&holiday)
+                && !holidays.contains(&holiday)
+            {
+                holidays.push(holiday);
+            }
+        }
+    }
+
+    Ok(Value::Number(sign * (count - holidays.len() as i64) as f64))
+}
+
+/// `DATEDIF(start, end, unit)` gives the time between two dates in whole
+/// years (`Y`), months (`M`) or days (`D`). `YM` and `YD` ignore the
+/// years and `MD` ignores the months as well.
+pub fn datedif(args: &[Value]) -> Result<Value, LangError> {
+    let (start, end) = (args[0].as_date()?, args[1].as_date()?);
+    if start > end {
+        return Err(LangError::num("The start is after the end"));
+    }
+
+    let mut months = month_index(end) - month_index(start);
+    if end.day() < start.day() {
+        months -= 1;
+    }
+    let days_since = |from: NaiveDate| end.signed_duration_since(from).num_days();
+
+    let unit = args[2].as_text()?.to_ascii_uppercase();
+    let out = match unit.as_str() {
+        "Y" => months / 12,
+        "M" => months,
+        "D" => days_since(start),
+        "YM" => months % 12,
+        "YD" => days_since(add_months(start, months / 12 * 12)?),
+        "MD" => days_since(add_months(start, months)?),
+        _ => return Err(LangError::num(format!("Unknown unit {}", unit))),
+    };
+    Ok(Value::Number(out as f64))
+}
+
+fn out_of_range() -> LangError {
+    LangError::num("The date is out of range")
+}
+
+/// More days than there are between the first and last dates, so that
+/// larger counts fail before `Duration::days` panics on them.
+const MAX_DAYS: i64 = 200_000_000;
+
+fn days(n: i64) -> Result<Duration, LangError> {
+    if n.abs() > MAX_DAYS {
+        return Err(out_of_range());
+    }
+    Ok(Duration::days(n))
+}
+
+/// Counts months from year 0, so that months can be added across years.
+fn month_index(date: NaiveDate) -> i64 {
+    date.year() as i64 * 12 + date.month0() as i64
+}
+
+fn first_of_month(index: i64) -> Result<NaiveDate, LangError> {
+    let year = i32::try_from(index.div_euclid(12)).map_err(|_| out_of_range())?;
+    let month = index.rem_euclid(12) as u32 + 1;
+    NaiveDate::from_ymd_opt(year, month, 1).ok_or_else(out_of_range)
+}
+
+/// Moves a date by whole months, keeping the day where the month allows.
+fn add_months(date: NaiveDate, months: i64) -> Result<NaiveDate, LangError> {
+    let index = month_index(date)
+        .checked_add(months)
+        .ok_or_else(out_of_range)?;
+    let last = first_of_month(index.checked_add(1).ok_or_else(out_of_range)?)?
+        .pred_opt()
+        .ok_or_else(out_of_range)?;
+    first_of_month(index)?
+        .with_day(date.day().min(last.day()))
+        .ok_or_else(out_of_range)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::Data;
+    use crate::parse::{parse, ErrorKind};
+    use crate::position::CellPos;
+
+    fn eval(input: &str, data: &Data) -> String {
+        match parse(input, data) {
+            Ok((val, _)) => val.to_string(),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_date_functions() {
+        let mut data = Data::new();
+        data.insert(CellPos::new(0, 0), "2026-01-31".to_string());
+
+        let cases = [
+            ("=DATE(2026, 10, 18)", "2026-10-18"),
+            ("=DATE(2026, 14, 1)", "2027-02-01"),
+            ("=DATE(2026, 3, 0)", "2026-02-28"),
+            (
+                "=YEAR(A1) & \"/\" & MONTH(A1) & \"/\" & DAY(A1)",
+                "2026/1/31",
+            ),
+            ("=MONTH(\"2026-10-18\")", "10"),
+            ("=EDATE(A1, 1)", "2026-02-28"),
+            ("=EDATE(A1, -2)", "2025-11-30"),
+            ("=EOMONTH(A1, 1)", "2026-02-28"),
+            ("=EOMONTH(\"2024-02-10\", 0)", "2024-02-29"),
+            ("=YEAR(TODAY()) > 2000", "TRUE"),
+            ("=NOW() >= TODAY()", "TRUE"),
+            ("=DATE(2026, 1, 1) - 1", "2025-12-31"),
+        ];
+        for (input, expected) in cases.iter() {
+            assert_eq!(eval(input, &data), *expected, "{}", input);
+        }
+
+        assert_eq!(
+            parse("=YEAR(\"soon\")", &data),
+            Err(ErrorKind::Value.into())
+        );
+
+        let out_of_range = [
+            "=DATE(2026, 1, 1.0e15)",
+            "=DATE(2026, 1, -1.0e18)",
+            "=DATE(1.0e18, 1, 1)",
+            "=EDATE(\"2026-01-01\", 1.0e19)",
+            "=EOMONTH(\"2026-01-01\", 1.0e19)",
+            "=EOMONTH(\"2026-01-01\", -1.0e19)",
+        ];
+        for input in out_of_range.iter() {
+            assert_eq!(parse(input, &data), Err(ErrorKind::Num.into()), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_date_arithmetic() {
+        let mut data = Data::new();
+        data.insert(CellPos::new(0, 0), "2026-10-18".to_string());
+        data.insert(CellPos::new(0, 1), "2026-12-25".to_string());
+        data.insert(CellPos::new(1, 0), "14:30".to_string());
+
+        let cases = [
+            ("=A2-A1", "68"),
+            ("=A1+14", "2026-11-01"),
+            ("=A1-18", "2026-09-30"),
+            ("=A1+B1", "2026-10-18 14:30"),
+            ("=B1+1/24", "15:30"),
+            ("=A2>A1", "TRUE"),
+            ("=MAX(A1:A2)-MIN(A1:A2)", "68"),
+            ("=sub A2 A1", "68"),
+            ("=add A1 14", "2026-11-01"),
+            ("=sub A1 18", "2026-09-30"),
+            ("=add A1 B1", "2026-10-18 14:30"),
+            ("=ADD(B1, 1/24)", "15:30"),
+        ];
+        for (input, expected) in cases.iter() {
+            assert_eq!(eval(input, &data), *expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_networkdays() {
+        let mut data = Data::new();
+        data.insert(CellPos::new(0, 0), "2026-12-25".to_string());
+        data.insert(CellPos::new(0, 1), "2026-12-26".to_string());
+
+        let cases = [
+            ("=NETWORKDAYS(\"2026-10-01\", \"2026-10-31\")", "22"),
+            ("=NETWORKDAYS(\"2026-10-17\", \"2026-10-18\")", "0"),
+            ("=NETWORKDAYS(\"2026-12-31\", \"2026-12-21\", A1:A2)", "-8"),
+        ];
+        for (input, expected) in cases.iter() {
+            assert_eq!(eval(input, &data), *expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_datedif() {
+        let data = Data::new();
+        let cases = [
+            ("=DATEDIF(\"2020-02-29\", \"2026-10-18\", \"Y\")", "6"),
+            ("=DATEDIF(\"2020-02-29\", \"2026-10-18\", \"M\")", "79"),
+            ("=DATEDIF(\"2026-01-01\", \"2026-10-18\", \"D\")", "290"),
+            ("=DATEDIF(\"2020-02-29\", \"2026-10-18\", \"ym\")", "7"),
+            ("=DATEDIF(\"2026-01-31\", \"2026-03-01\", \"MD\")", "1"),
+            ("=DATEDIF(\"2025-12-20\", \"2026-10-18\", \"YD\")", "302"),
+        ];
+        for (input, expected) in cases.iter() {
+            assert_eq!(eval(input, &data), *expected, "{}", input);
+        }
+
+        let backwards = parse("=DATEDIF(\"2026-10-18\", \"2026-01-01\", \"D\")", &data);
+        assert_eq!(backwards, Err(ErrorKind::Num.into()));
+    }
+}
@@ -0,0 +1,247 @@
+use super::{numbers, optional, Arity, Registry};
+use crate::parse::LangError;
+use crate::value::Value;
+
+pub fn register(registry: &mut Registry) {
+    registry.builtin("PMT", Arity::range(3, 5), &[], pmt);
+    registry.builtin("FV", Arity::range(3, 5), &[], fv);
+    registry.builtin("PV", Arity::range(3, 5), &[], pv);
+    registry.builtin("NPV", Arity::at_least(2), &[], npv);
+    registry.builtin("IRR", Arity::range(1, 2), &[], irr);
+    registry.builtin("XNPV", Arity::exactly(3), &[], xnpv);
+    registry.builtin("RATE", Arity::range(3, 6), &[], rate);
+}
+
+const MAX_ITERATIONS: usize = 100;
+const TOLERANCE: f64 = 1e-10;
+
+/// `PMT(rate, periods, present, [future], [type])` is the payment each
+/// period that pays off a loan. Type 1 means payments at the start of each
+/// period rather than the end. Money paid out is negative.
+pub fn pmt(args: &[Value]) -> Result<Value, LangError> {
+    let (rate, periods, present) = (number(args, 0)?, number(args, 1)?, number(args, 2)?);
+    let (future, due) = (optional(args, 3, 0.0)?, due(args, 4)?);
+    if periods == 0.0 {
+        return Err(LangError::num("The number of periods cannot be 0"));
+    }
+
+    let out = if rate == 0.0 {
+        -(present + future) / periods
+    } else {
+        let growth = (1.0 + rate).powf(periods);
+        -rate * (future + present * growth) / ((1.0 + rate * due) * (growth - 1.0))
+    };
+    finite(out)
+}
+
+/// `FV(rate, periods, payment, [present], [type])` is the value after the
+/// last period.
+pub fn fv(args: &[Value]) -> Result<Value, LangError> {
+    let (rate, periods, payment) = (number(args, 0)?, number(args, 1)?, number(args, 2)?);
+    let (present, due) = (optional(args, 3, 0.0)?, due(args, 4)?);
+    finite(future_value(rate, periods, payment, present, due))
+}
+
+/// `PV(rate, periods, payment, [future], [type])` is what the payments are
+/// worth today.
+pub fn pv(args: &[Value]) -> Result<Value, LangError> {
+    let (rate, periods, payment) = (number(args, 0)?, number(args, 1)?, number(args, 2)?);
+    let (future, due) = (optional(args, 3, 0.0)?, due(args, 4)?);
+
+    let out = if rate == 0.0 {
+        -(future + payment * periods)
+    } else {
+        let growth = (1.0 + rate).powf(periods);
+        -(future + payment * (1.0 + rate * due) * (growth - 1.0) / rate) / growth
+    };
+    finite(out)
+}
+
+/// `NPV(rate, value1, ...)` discounts values received at the end of each
+/// period, the first one period from now.
+pub fn npv(args: &[Value]) -> Result<Value, LangError> {
+    let rate = number(args, 0)?;
+    let vals = numbers(&args[1..])?;
+    finite(present_value(rate, &vals, 1))
+}
+
+/// `IRR(values, [guess])` is the rate at which the net present value of the
+/// values, one per period starting now, is zero.
+pub fn irr(args: &[Value]) -> Result<Value, LangError> {
+    let vals = numbers(&args[..1])?;
+    if !vals.iter().any(|v| *v > 0.0) || !vals.iter().any(|v| *v < 0.0) {
+        return Err(LangError::num(
+            "IRR needs both positive and negative values",
+        ));
+    }
+
+    let guess = optional(args, 1, 0.1)?;
+    solve(|rate| present_value(rate, &vals, 0), guess).map(Value::Number)
+}
+
+/// `XNPV(rate, values, dates)` discounts each value by the years between
+/// its date and the first date.
+pub fn xnpv(args: &[Value]) -> Result<Value, LangError> {
+    let rate = number(args, 0)?;
+    let vals = numbers(&args[1..2])?;
+    let dates = numbers(&args[2..3])?;
+    if vals.is_empty() || vals.len() != dates.len() {
+        return Err(LangError::num("There must be a date for every value"));
+    }
+    if rate <= -1.0 {
+        return Err(LangError::num("The rate must be greater than -1"));
+    }
+
+    let out = vals
+        .iter()
+        .zip(&dates)
+        .map(|(val, date)| val / (1.0 + rate).powf((date - dates[0]) / 365.0))
+        .sum();
+    finite(out)
+}
+
+/// `RATE(periods, payment, present, [future], [type], [guess])` is the rate
+/// per period that makes the payments pay off the present value.
+pub fn rate(args: &[Value]) -> Result<Value, LangError> {
+    let (periods, payment, present) = (number(args, 0)?, number(args, 1)?, number(args, 2)?);
+    let (future, due) = (optional(args, 3, 0.0)?, due(args, 4)?);
+    let guess = optional(args, 5, 0.1)?;
+
+    let balance = |rate| future_value(rate, periods, payment, present, due) + future;
+    solve(balance, guess).map(Value::Number)
+}
+
+fn future_value(rate: f64, periods: f64, payment: f64, present: f64, due: f64) -> f64 {
+    if rate == 0.0 {
+        -(present + payment * periods)
+    } else {
+        let growth = (1.0 + rate).powf(periods);
+        -(present * growth + payment * (1.0 + rate * due) * (growth - 1.0) / rate)
+    }
+}
+
+/// Discounts one value per period, the first `first` periods from now.
+fn present_value(rate: f64, vals: &[f64], first: i32) -> f64 {
+    vals.iter()
+        .zip(first..)
+        .map(|(val, period)| val / (1.0 + rate).powi(period))
+        .sum()
+}
+
+/// Finds a rate where `f` is zero with Newton's method, starting from
+/// `guess`. Fails with `#NUM!` if it does not converge.
+fn solve(f: impl Fn(f64) -> f64, guess: f64) -> Result<f64, LangError> {
+    // Steps also get small when closing in on -100% without a root there
+    let close_enough = 1e-7 * f(guess).abs().max(1.0);
+    let mut rate = guess;
+    for _ in 0..MAX_ITERATIONS {
+        let val = f(rate);
+        let step = 1e-6 * rate.abs().max(1e-3);
+        let slope = (f(rate + step) - val) / step;
+        if !val.is_finite() || !slope.is_finite() || slope == 0.0 {
+            break;
+        }
+
+        let mut next = rate - val / slope;
+        // Rates at or below -100% are meaningless, so approach it instead
+        if next <= -1.0 {
+            next = (rate - 1.0) / 2.0;
+        }
+        if (next - rate).abs() < TOLERANCE {
+            if f(next).abs() < close_enough {
+                return Ok(next);
+            }
+            break;
+        }
+        rate = next;
+    }
+
+    Err(LangError::num(format!(
+        "The rate did not converge from a guess of {}",
+        guess
+    )))
+}
+
+fn number(args: &[Value], index: usize) -> Result<f64, LangError> {
+    args[index].as_number()
+}
+
+/// Whether payments are made at the start of each period: 1 if so, else 0.
+fn due(args: &[Value], index: usize) -> Result<f64, LangError> {
+    Ok(if optional(args, index, 0.0)? != 0.0 {
+        1.0
+    } else {
+        0.0
+    })
+}
+
+fn finite(n: f64) -> Result<Value, LangError> {
+    if n.is_finite() {
+        Ok(Value::Number(n))
+    } else {
+        Err(LangError::num("The result is not a finite number"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::Data;
+    use crate::functions::testing::assert_close;
+    use crate::parse::{parse, ErrorKind};
+    use crate::position::CellPos;
+
+    #[test]
+    fn test_loans() {
+        let data = Data::new();
+        assert_close("=PMT(5%/12, 360, 200000)", -1073.643246, 1e-6, &data);
+        assert_close("=PMT(0, 10, 1000)", -100.0, 1e-9, &data);
+        assert_close("=FV(6%/12, 10, -200, -500, 1)", 2581.403374, 1e-6, &data);
+        assert_close("=PV(8%/12, 240, 500)", -59777.145851, 1e-6, &data);
+        assert_close("=RATE(48, -200, 8000)", 0.007701472, 1e-9, &data);
+        assert_close(
+            "=RATE(360, PMT(0.004, 360, 1000), 1000)",
+            0.004,
+            1e-9,
+            &data,
+        );
+        assert_eq!(
+            parse("=RATE(10, 100, 1000)", &data),
+            Err(ErrorKind::Num.into())
+        );
+    }
+
+    #[test]
+    fn test_cash_flows() {
+        let mut data = Data::new();
+        let flows = ["-70000", "12000", "15000", "18000", "21000", "26000"];
+        for (y, val) in flows.iter().enumerate() {
+            data.insert(CellPos::new(0, y), val.to_string());
+        }
+        let dated = [
+            ("-10000", "2008-01-01"),
+            ("2750", "2008-03-01"),
+            ("4250", "2008-10-30"),
+            ("3250", "2009-02-15"),
+            ("2750", "2009-04-01"),
+        ];
+        for (y, (val, date)) in dated.iter().enumerate() {
+            data.insert(CellPos::new(1, y), val.to_string());
+            data.insert(CellPos::new(2, y), date.to_string());
+        }
+
+        assert_close(
+            "=NPV(10%, -10000, 3000, 4200, 6800)",
+            1188.443412,
+            1e-6,
+            &data,
+        );
+        assert_close("=IRR(A1:A6)", 0.086630948, 1e-9, &data);
+        assert_close("=IRR(A1:A5, -0.1)", -0.021244848, 1e-9, &data);
+        assert_close("=XNPV(0.09, B1:B5, C1:C5)", 2086.647602, 1e-6, &data);
+        assert_eq!(parse("=IRR(A2:A6)", &data), Err(ErrorKind::Num.into()));
+        assert_eq!(
+            parse("=XNPV(0.09, B1:B5, C1:C4)", &data),
+            Err(ErrorKind::Num.into())
+        );
+    }
+}
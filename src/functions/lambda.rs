@@ -0,0 +1,148 @@
+use super::{Arity, Function, Registry};
+use crate::parse::{Args, LangError};
+use crate::value::Value;
+
+pub fn register(registry: &mut Registry) {
+    registry.register(Let);
+    registry.register(Lambda);
+}
+
+/// `LET(name1, value1, ..., body)` names values for use in later arguments.
+pub struct Let;
+
+impl Function for Let {
+    fn name(&self) -> &str {
+        "LET"
+    }
+
+    fn arity(&self) -> Arity {
+        Arity::at_least(3)
+    }
+
+    fn evaluate(&self, args: &mut Args) -> Result<Value, LangError> {
+        if args.len().is_multiple_of(2) {
+            return Err(LangError::value("LET needs a value for every name"));
+        }
+
+        let body = args.len() - 1;
+        for i in (0..body).step_by(2) {
+            let name = args
+                .identifier(i)
+                .ok_or_else(|| LangError::value("LET needs a name"))?;
+            let val = args.eval(i + 1)?;
+            args.bind(name, val);
+        }
+        args.eval(body)
+    }
+}
+
+/// `LAMBDA(param1, ..., body)` makes a function out of its last argument.
+pub struct Lambda;
+
+impl Function for Lambda {
+    fn name(&self) -> &str {
+        "LAMBDA"
+    }
+
+    fn arity(&self) -> Arity {
+        Arity::at_least(1)
+    }
+
+    fn evaluate(&self, args: &mut Args) -> Result<Value, LangError> {
+        let body = args.len() - 1;
+        let mut params: Vec<String> = Vec::new();
+        for i in 0..body {
+            let param = args
+                .identifier(i)
+                .ok_or_else(|| LangError::value("LAMBDA parameters must be names"))?;
+            if params.contains(&param) {
+                return Err(LangError::value(format!("{} is repeated", param)));
+            }
+            params.push(param);
+        }
+        Ok(args.lambda(params, body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::Data;
+    use crate::parse::{parse, ErrorKind};
+    use crate::position::CellPos;
+    use crate::value::Value;
+
+    #[test]
+    fn test_let() {
+        let mut data = Data::new();
+        data.insert(CellPos::new(0, 0), "4".to_string());
+
+        let cases = [
+            ("=LET(x, A1*2, x+x)", 16.0),
+            ("=LET(x, 2, y, x*3, x+y)", 8.0),
+            ("=LET(x, 1, LET(x, x+1, x*10) + x)", 21.0),
+            ("=let a1_sq (mul A1 A1) (add a1_sq 1)", 17.0),
+        ];
+        for (input, expected) in cases.iter() {
+            let (val, _) = parse(input, &data).unwrap();
+            assert_eq!(val, Value::Number(*expected), "{}", input);
+        }
+
+        assert_eq!(parse("=LET(x, 1, y)", &data), Err(ErrorKind::Name.into()));
+        assert_eq!(parse("=LET(A1, 1, 2)", &data), Err(ErrorKind::Value.into()));
+        assert_eq!(
+            parse("=LET(x, 1, y, 2)", &data),
+            Err(ErrorKind::Value.into())
+        );
+    }
+
+    #[test]
+    fn test_lambda() {
+        let mut data = Data::new();
+        data.insert(CellPos::new(0, 0), "3".to_string());
+
+        let cases = [
+            ("=LET(sq, LAMBDA(n, n*n), sq(A1) + sq(2))", 13.0),
+            (
+                "=LET(k, 10, add_k, LAMBDA(n, n+k), LET(k, 0, add_k(1)))",
+                11.0,
+            ),
+            ("=LET(f, LAMBDA(2), f())", 2.0),
+        ];
+        for (input, expected) in cases.iter() {
+            let (val, _) = parse(input, &data).unwrap();
+            assert_eq!(val, Value::Number(*expected), "{}", input);
+        }
+
+        let wrong_args = parse("=LET(f, LAMBDA(a, b, a+b), f(1))", &data);
+        assert_eq!(wrong_args, Err(ErrorKind::Value.into()));
+        assert_eq!(parse("=LAMBDA(x, x)", &data), Err(ErrorKind::Value.into()));
+        assert_eq!(
+            parse("=LAMBDA(x, x, 1)", &data),
+            Err(ErrorKind::Value.into())
+        );
+    }
+
+    #[test]
+    fn test_named_lambda() {
+        let mut data = Data::new();
+        data.insert(CellPos::new(0, 0), "5".to_string());
+        data.define_name("fact", "=LAMBDA(n, IF(n<=1, 1, n*fact(n-1)))")
+            .unwrap();
+        data.insert(CellPos::new(1, 0), "=FACT(A1)".to_string());
+        assert_eq!(data.get(CellPos::new(1, 0)), Some(&Value::Number(120.0)));
+
+        data.insert(CellPos::new(0, 0), "4".to_string());
+        assert_eq!(data.get(CellPos::new(1, 0)), Some(&Value::Number(24.0)));
+
+        data.define_name("fact", "=LAMBDA(n, n)").unwrap();
+        assert_eq!(data.get(CellPos::new(1, 0)), Some(&Value::Number(4.0)));
+
+        data.define_name("forever", "=LAMBDA(n, forever(n+1))")
+            .unwrap();
+        assert_eq!(parse("=forever(1)", &data), Err(ErrorKind::Num.into()));
+        assert_eq!(
+            data.define_name("bad", "=LAMBDA(n,"),
+            Err(ErrorKind::Parse.into())
+        );
+    }
+}
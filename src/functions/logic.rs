@@ -0,0 +1,299 @@
+use super::{booleans, Arity, Coerce, Function, Registry};
+use crate::parse::Args;
+use crate::parse::{ErrorKind, LangError};
+use crate::value::Value;
+use std::cmp::Ordering;
+
+pub fn register(registry: &mut Registry) {
+    registry.register(If);
+    registry.register(Ifs);
+    registry.register(Switch);
+    registry.register(IfError {
+        name: "IFERROR",
+        na_only: false,
+    });
+    registry.register(IfError {
+        name: "IFNA",
+        na_only: true,
+    });
+    registry.register(IsError {
+        name: "ISERROR",
+        na_only: false,
+    });
+    registry.register(IsError {
+        name: "ISNA",
+        na_only: true,
+    });
+    registry.builtin("AND", Arity::at_least(1), &[], and);
+    registry.builtin("OR", Arity::at_least(1), &[], or);
+    registry.builtin("XOR", Arity::at_least(1), &[], xor);
+    registry.builtin("NOT", Arity::exactly(1), &[Coerce::Bool], not);
+    registry.builtin("TRUE", Arity::exactly(0), &[], true_);
+    registry.builtin("FALSE", Arity::exactly(0), &[], false_);
+}
+
+/// `IF(cond, then, [else])` only evaluates the branch it takes.
+pub struct If;
+
+impl Function for If {
+    fn name(&self) -> &str {
+        "IF"
+    }
+
+    fn arity(&self) -> Arity {
+        Arity::range(2, 3)
+    }
+
+    fn coerce(&self, index: usize) -> Coerce {
+        if index == 0 {
+            Coerce::Bool
+        } else {
+            Coerce::Any
+        }
+    }
+
+    fn evaluate(&self, args: &mut Args) -> Result<Value, LangError> {
+        let (taken, skipped) = if args.eval(0)?.as_bool()? {
+            (1, 2)
+        } else {
+            (2, 1)
+        };
+
+        args.skip(skipped);
+        if taken < args.len() {
+            args.eval(taken)
+        } else {
+            Ok(Value::Bool(false))
+        }
+    }
+}
+
+/// `IFS(cond1, value1, cond2, value2, ...)` takes the first true condition.
+pub struct Ifs;
+
+impl Function for Ifs {
+    fn name(&self) -> &str {
+        "IFS"
+    }
+
+    fn arity(&self) -> Arity {
+        Arity::at_least(2)
+    }
+
+    fn coerce(&self, index: usize) -> Coerce {
+        if index.is_multiple_of(2) {
+            Coerce::Bool
+        } else {
+            Coerce::Any
+        }
+    }
+
+    fn evaluate(&self, args: &mut Args) -> Result<Value, LangError> {
+        if !args.len().is_multiple_of(2) {
+            return Err(LangError::value("IFS needs a value for every condition"));
+        }
+
+        for i in (0..args.len()).step_by(2) {
+            if args.eval(i)?.as_bool()? {
+                (i + 2..args.len()).for_each(|j| args.skip(j));
+                return args.eval(i + 1);
+            }
+            args.skip(i + 1);
+        }
+        Err(LangError::na("No condition is true"))
+    }
+}
+
+/// `SWITCH(value, case1, result1, ..., [default])` takes the first case
+/// equal to the value.
+pub struct Switch;
+
+impl Function for Switch {
+    fn name(&self) -> &str {
+        "SWITCH"
+    }
+
+    fn arity(&self) -> Arity {
+        Arity::at_least(3)
+    }
+
+    fn evaluate(&self, args: &mut Args) -> Result<Value, LangError> {
+        let subject = args.eval(0)?;
+        let default = if args.len().is_multiple_of(2) {
+            Some(args.len() - 1)
+        } else {
+            None
+        };
+
+        let cases = default.unwrap_or_else(|| args.len());
+        for i in (1..cases).step_by(2) {
+            if subject.compare(&args.eval(i)?)? == Ordering::Equal {
+                (i + 2..args.len()).for_each(|j| args.skip(j));
+                return args.eval(i + 1);
+            }
+            args.skip(i + 1);
+        }
+
+        match default {
+            Some(default) => args.eval(default),
+            None => Err(LangError::na("No case matches")),
+        }
+    }
+}
+
+/// An error caught by IFERROR and ISERROR, or by IFNA and ISNA when
+/// `na_only` is set.
+fn is_caught(val: &Value, na_only: bool) -> bool {
+    match val {
+        Value::Error(e) => !na_only || e.kind == ErrorKind::Na,
+        _ => false,
+    }
+}
+
+/// The first argument, holding any error it gave instead of failing.
+fn eval_caught(args: &mut Args) -> Value {
+    args.eval(0).unwrap_or_else(Value::Error)
+}
+
+/// `IFERROR(value, fallback)` gives the fallback in place of an error,
+/// element by element for an array. The fallback is only evaluated when
+/// it is needed. `IFNA` only replaces `#N/A`.
+pub struct IfError {
+    name: &'static str,
+    na_only: bool,
+}
+
+impl Function for IfError {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn arity(&self) -> Arity {
+        Arity::exactly(2)
+    }
+
+    fn evaluate(&self, args: &mut Args) -> Result<Value, LangError> {
+        let val = eval_caught(args);
+        if !val.iter().any(|v| is_caught(v, self.na_only)) {
+            args.skip(1);
+            return match val {
+                Value::Error(e) => Err(e),
+                v => Ok(v),
+            };
+        }
+
+        let fallback = args.eval(1)?;
+        Ok(val.map(|v| {
+            if is_caught(v, self.na_only) {
+                fallback.clone()
+            } else {
+                v.clone()
+            }
+        }))
+    }
+}
+
+/// `ISERROR(value)` is true for an error, element by element for an array.
+/// `ISNA` is only true for `#N/A`.
+pub struct IsError {
+    name: &'static str,
+    na_only: bool,
+}
+
+impl Function for IsError {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn arity(&self) -> Arity {
+        Arity::exactly(1)
+    }
+
+    fn evaluate(&self, args: &mut Args) -> Result<Value, LangError> {
+        let val = eval_caught(args);
+        Ok(val.map(|v| Value::Bool(is_caught(v, self.na_only))))
+    }
+}
+
+pub fn and(args: &[Value]) -> Result<Value, LangError> {
+    let vals = booleans(args)?;
+    Ok(Value::Bool(vals.iter().all(|b| *b)))
+}
+
+pub fn or(args: &[Value]) -> Result<Value, LangError> {
+    let vals = booleans(args)?;
+    Ok(Value::Bool(vals.iter().any(|b| *b)))
+}
+
+/// True when an odd number of the arguments are true.
+pub fn xor(args: &[Value]) -> Result<Value, LangError> {
+    let vals = booleans(args)?;
+    Ok(Value::Bool(vals.iter().filter(|b| **b).count() % 2 == 1))
+}
+
+pub fn not(args: &[Value]) -> Result<Value, LangError> {
+    Ok(Value::Bool(!args[0].as_bool()?))
+}
+
+pub fn true_(_args: &[Value]) -> Result<Value, LangError> {
+    Ok(Value::Bool(true))
+}
+
+pub fn false_(_args: &[Value]) -> Result<Value, LangError> {
+    Ok(Value::Bool(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Data;
+    use crate::parse::parse;
+    use crate::position::CellPos;
+
+    #[test]
+    fn test_logic() {
+        let range = Value::Array(vec![vec![
+            Value::Bool(true),
+            Value::Text("x".to_string()),
+            Value::Number(0.0),
+        ]]);
+        assert_eq!(and(std::slice::from_ref(&range)), Ok(Value::Bool(false)));
+        assert_eq!(or(std::slice::from_ref(&range)), Ok(Value::Bool(true)));
+        assert_eq!(xor(&[range, Value::Bool(true)]), Ok(Value::Bool(false)));
+        assert_eq!(not(&[Value::Number(0.0)]), Ok(Value::Bool(true)));
+        assert_eq!(
+            and(&[Value::Array(vec![vec![Value::Empty]])]),
+            Err(ErrorKind::Value.into())
+        );
+        assert_eq!(
+            or(&[Value::Text("x".to_string())]),
+            Err(ErrorKind::Value.into())
+        );
+    }
+
+    #[test]
+    fn test_iferror() {
+        let mut data = Data::new();
+        data.insert(CellPos::new(0, 0), "=1/0".to_string());
+        data.insert(CellPos::new(0, 1), "2".to_string());
+
+        let cases = [
+            ("=IFERROR(A1, \"none\")", Value::Text("none".to_string())),
+            ("=IFERROR(A2, 1/0)", Value::Number(2.0)),
+            ("=IFERROR(NOPE(), 0)", Value::Number(0.0)),
+            ("=IFNA(MATCH(5, A2:A2, 0), -1)", Value::Number(-1.0)),
+            ("=ISERROR(A1 + 1)", Value::Bool(true)),
+            ("=ISERROR(A2)", Value::Bool(false)),
+            ("=ISNA(A1)", Value::Bool(false)),
+            ("=SUM(IFERROR(1/A1:A2, 0))", Value::Number(0.5)),
+        ];
+        for (input, expected) in cases.iter() {
+            let (val, _) = parse(input, &data).unwrap();
+            assert_eq!(&val, expected, "{}", input);
+        }
+
+        let err = parse("=IFNA(A1, 0)", &data).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Div);
+        assert_eq!(err.reason, "Division by zero");
+    }
+}
@@ -0,0 +1,471 @@
+use super::{rows, Arity, Coerce::*, Registry};
+use crate::parse::{ErrorKind, LangError};
+use crate::value::Value;
+use std::cmp::Ordering;
+
+pub fn register(registry: &mut Registry) {
+    registry.builtin(
+        "VLOOKUP",
+        Arity::range(3, 4),
+        &[Any, Any, Number, Bool],
+        vlookup,
+    );
+    registry.builtin(
+        "HLOOKUP",
+        Arity::range(3, 4),
+        &[Any, Any, Number, Bool],
+        hlookup,
+    );
+    registry.builtin(
+        "XLOOKUP",
+        Arity::range(3, 6),
+        &[Any, Any, Any, Any, Number],
+        xlookup,
+    );
+    registry.builtin("INDEX", Arity::range(2, 3), &[Any, Number], index);
+    registry.builtin("MATCH", Arity::range(2, 3), &[Any, Any, Number], match_);
+}
+
+/// How a lookup value is matched against the searched values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Exact,
+    /// An exact match where `*` and `?` in text match any characters.
+    Wildcard,
+    /// An exact match or else the largest value below it.
+    NextSmaller,
+    /// An exact match or else the smallest value above it.
+    NextLarger,
+}
+
+/// `VLOOKUP(value, table, column, [approximate])` looks the value up in the
+/// first column of the table.
+pub fn vlookup(args: &[Value]) -> Result<Value, LangError> {
+    let table = rows(&args[1]);
+    let first: Vec<_> = table
+        .iter()
+        .map(|row| row.first().cloned().unwrap_or_default())
+        .collect();
+    let row = find(&args[0], &first, table_mode(args.get(3))?, false)?;
+    let col = position(&args[2])?;
+    table[row]
+        .get(col)
+        .cloned()
+        .ok_or_else(|| LangError::reference(format!("There is no column {}", col + 1)))
+}
+
+/// `HLOOKUP(value, table, row, [approximate])` looks the value up in the
+/// first row of the table.
+pub fn hlookup(args: &[Value]) -> Result<Value, LangError> {
+    let table = rows(&args[1]);
+    let first = table.first().cloned().unwrap_or_default();
+    let col = find(&args[0], &first, table_mode(args.get(3))?, false)?;
+    let row = position(&args[2])?;
+    table
+        .get(row)
+        .and_then(|row| row.get(col))
+        .cloned()
+        .ok_or_else(|| LangError::reference(format!("There is no row {}", row + 1)))
+}
+
+/// `XLOOKUP(value, lookup, result, [if_not_found], [match_mode],
+/// [search_mode])` finds the value in a row or column and returns the
+/// matching entry of `result`. Match mode 0 is exact, -1 and 1 also accept
+/// the next smaller or larger value and 2 allows wildcards. A negative
+/// search mode searches from the end.
+pub fn xlookup(args: &[Value]) -> Result<Value, LangError> {
+    let lookup = rows(&args[1]);
+    let is_row = lookup.len() == 1 && lookup[0].len() > 1;
+    let haystack = vector(&args[1])?;
+
+    let mode = match args.get(4).map(|m| m.as_number()).transpose()? {
+        None | Some(0.0) => Mode::Exact,
+        Some(-1.0) => Mode::NextSmaller,
+        Some(1.0) => Mode::NextLarger,
+        Some(2.0) => Mode::Wildcard,
+        _ => return Err(LangError::value("The match mode must be -1, 0, 1 or 2")),
+    };
+    let reverse = match args.get(5) {
+        Some(search) => search.as_number()? < 0.0,
+        None => false,
+    };
+
+    let found = match find(&args[0], &haystack, mode, reverse) {
+        Err(e) if e.kind == ErrorKind::Na => match args.get(3) {
+            Some(Value::Empty) | None => return Err(e),
+            Some(fallback) => return Ok(fallback.clone()),
+        },
+        found => found?,
+    };
+
+    let result = rows(&args[2]);
+    let mismatch = || LangError::value("The return range does not match the lookup range");
+    let out = if is_row {
+        if result.first().map_or(0, |r| r.len()) != haystack.len() {
+            return Err(mismatch());
+        }
+        result.iter().map(|row| vec![row[found].clone()]).collect()
+    } else {
+        if result.len() != haystack.len() {
+            return Err(mismatch());
+        }
+        vec![result[found].clone()]
+    };
+    Ok(single(out))
+}
+
+/// `INDEX(table, row, [column])` picks a value out of a range, counting
+/// from 1. A row or column of 0 picks the whole column or row.
+pub fn index(args: &[Value]) -> Result<Value, LangError> {
+    let table = rows(&args[0]);
+    let (row, col) = match args.get(2) {
+        Some(col) => (count(&args[1])?, count(col)?),
+        // A single row is indexed by column
+        None if table.len() == 1 => (1, count(&args[1])?),
+        None => (count(&args[1])?, 1),
+    };
+
+    let height = table.len();
+    let width = table.first().map_or(0, |r| r.len());
+    if row > height || col > width {
+        return Err(LangError::reference("The position is outside the range"));
+    }
+
+    let out = match (row, col) {
+        (0, 0) => table,
+        (0, col) => table.iter().map(|r| vec![r[col - 1].clone()]).collect(),
+        (row, 0) => vec![table[row - 1].clone()],
+        (row, col) => return Ok(table[row - 1][col - 1].clone()),
+    };
+    Ok(single(out))
+}
+
+/// `MATCH(value, range, [type])` gives the position of the value in a row
+/// or column, counting from 1. Type 1, the default, finds the largest value
+/// not above it in ascending data, -1 the smallest value not below it in
+/// descending data and 0 an exact match allowing wildcards.
+pub fn match_(args: &[Value]) -> Result<Value, LangError> {
+    let mode = match args.get(2).map(|m| m.as_number()).transpose()? {
+        None => Mode::NextSmaller,
+        Some(n) if n > 0.0 => Mode::NextSmaller,
+        Some(n) if n < 0.0 => Mode::NextLarger,
+        Some(_) => Mode::Wildcard,
+    };
+    let found = find(&args[0], &vector(&args[1])?, mode, false)?;
+    Ok(Value::Number((found + 1) as f64))
+}
+
+/// The mode of VLOOKUP and HLOOKUP, which match approximately by default.
+fn table_mode(approximate: Option<&Value>) -> Result<Mode, LangError> {
+    match approximate {
+        Some(val) if !val.as_bool()? => Ok(Mode::Wildcard),
+        _ => Ok(Mode::NextSmaller),
+    }
+}
+
+/// Finds the position of `needle` in `haystack`, or fails with `#N/A`.
+/// Only values of the same kind as the needle are considered.
+fn find(needle: &Value, haystack: &[Value], mode: Mode, reverse: bool) -> Result<usize, LangError> {
+    if let Value::Error(e) = needle {
+        return Err(e.clone());
+    }
+
+    let mut order: Vec<usize> = (0..haystack.len()).collect();
+    if reverse {
+        order.reverse();
+    }
+
+    let mut best: Option<usize> = None;
+    for i in order {
+        let val = &haystack[i];
+        if kind(val) != kind(needle) || kind(needle).is_none() {
+            continue;
+        }
+
+        let ord = val.compare(needle)?;
+        let matched = match (mode, needle, val) {
+            (Mode::Wildcard, Value::Text(pattern), Value::Text(text)) => {
+                wildcard_match(pattern, text)
+            }
+            _ => ord == Ordering::Equal,
+        };
+        if matched {
+            return Ok(i);
+        }
+
+        let wanted = match mode {
+            Mode::NextSmaller => Ordering::Less,
+            Mode::NextLarger => Ordering::Greater,
+            _ => continue,
+        };
+        let better = match best {
+            Some(b) => haystack[i].compare(&haystack[b])? == wanted.reverse(),
+            None => true,
+        };
+        if ord == wanted && better {
+            best = Some(i);
+        }
+    }
+    best.ok_or_else(|| LangError::na(format!("{} was not found", needle)))
+}
+
+/// Groups values that can be compared with each other in a lookup.
+fn kind(val: &Value) -> Option<u8> {
+    match val {
+        v if v.is_numeric() => Some(0),
+        Value::Text(_) => Some(1),
+        Value::Bool(_) => Some(2),
+        _ => None,
+    }
+}
+
+/// Matches text against a pattern where `*` is any run of characters, `?`
+/// any single character and `~` escapes the next character, ignoring case.
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    enum Token {
+        Char(char),
+        One,
+        Many,
+    }
+
+    let mut tokens = Vec::new();
+    let mut chars = pattern.chars().flat_map(char::to_lowercase);
+    while let Some(c) = chars.next() {
+        tokens.push(match c {
+            '*' => Token::Many,
+            '?' => Token::One,
+            '~' => Token::Char(chars.next().unwrap_or('~')),
+            c => Token::Char(c),
+        });
+    }
+
+    // matches[j] is whether the tokens so far match the first j characters
+    let text: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+    let mut matches = vec![false; text.len() + 1];
+    matches[0] = true;
+    for token in tokens {
+        let mut next = vec![false; text.len() + 1];
+        for j in 0..=text.len() {
+            next[j] = match token {
+                Token::Many => matches[j] || (j > 0 && next[j - 1]),
+                Token::One => j > 0 && matches[j - 1],
+                Token::Char(c) => j > 0 && matches[j - 1] && text[j - 1] == c,
+            };
+        }
+        matches = next;
+    }
+    matches[text.len()]
+}
+
+/// The values of a single row or column.
+fn vector(val: &Value) -> Result<Vec<Value>, LangError> {
+    let rows = rows(val);
+    if rows.len() == 1 || rows.iter().all(|r| r.len() == 1) {
+        Ok(rows.into_iter().flatten().collect())
+    } else {
+        Err(LangError::na(
+            "The lookup range must be a single row or column",
+        ))
+    }
+}
+
+/// Unwraps a result holding one value, leaving anything larger an array.
+fn single(mut rows: Vec<Vec<Value>>) -> Value {
+    if rows.len() == 1 && rows[0].len() == 1 {
+        rows.remove(0).remove(0)
+    } else {
+        Value::Array(rows)
+    }
+}
+
+/// A 1-based position, where 0 is allowed.
+fn count(val: &Value) -> Result<usize, LangError> {
+    let n = val.as_number()?.trunc();
+    if n < 0.0 {
+        Err(LangError::value("The position cannot be negative"))
+    } else {
+        Ok(n as usize)
+    }
+}
+
+/// A 1-based position, turned into an index.
+fn position(val: &Value) -> Result<usize, LangError> {
+    match count(val)? {
+        0 => Err(LangError::value("Positions start at 1")),
+        n => Ok(n - 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Data;
+    use crate::functions::testing::{eval, sheet};
+    use crate::position::CellPos;
+
+    /// A price table in A1:C4 with a header row.
+    fn prices() -> Data {
+        sheet(&[
+            &["Item", "Price", "Stock"],
+            &["apple", "0.5", "10"],
+            &["banana", "0.25", "0"],
+            &["cherry", "3", "25"],
+        ])
+    }
+
+    #[test]
+    fn test_vlookup_and_hlookup() {
+        let data = prices();
+        assert_eq!(
+            eval("=VLOOKUP(\"cherry\", A2:C4, 2, FALSE)", &data),
+            Ok(Value::Number(3.0))
+        );
+        assert_eq!(
+            eval("=VLOOKUP(\"BAN*\", A2:C4, 3, FALSE)", &data),
+            Ok(Value::Number(0.0))
+        );
+        assert_eq!(
+            eval("=VLOOKUP(\"kiwi\", A2:C4, 2, FALSE)", &data),
+            Err(ErrorKind::Na.into())
+        );
+        assert_eq!(
+            eval("=VLOOKUP(\"apple\", A2:C4, 4, FALSE)", &data),
+            Err(ErrorKind::Ref.into())
+        );
+        assert_eq!(
+            eval("=HLOOKUP(\"Stock\", A1:C4, 4, FALSE)", &data),
+            Ok(Value::Number(25.0))
+        );
+
+        // Approximate matches find the largest value not above the needle
+        let mut data = Data::new();
+        for (y, (limit, rate)) in [("0", "0"), ("1000", "0.1"), ("5000", "0.2")]
+            .iter()
+            .enumerate()
+        {
+            data.insert(CellPos::new(0, y), limit.to_string());
+            data.insert(CellPos::new(1, y), rate.to_string());
+        }
+        assert_eq!(
+            eval("=VLOOKUP(4999, A1:B3, 2)", &data),
+            Ok(Value::Number(0.1))
+        );
+        assert_eq!(
+            eval("=VLOOKUP(5000, A1:B3, 2, TRUE)", &data),
+            Ok(Value::Number(0.2))
+        );
+        assert_eq!(
+            eval("=VLOOKUP(-1, A1:B3, 2)", &data),
+            Err(ErrorKind::Na.into())
+        );
+    }
+
+    #[test]
+    fn test_index_and_match() {
+        let data = prices();
+        assert_eq!(
+            eval("=MATCH(\"banana\", A1:A4, 0)", &data),
+            Ok(Value::Number(3.0))
+        );
+        assert_eq!(
+            eval("=MATCH(\"?rice\", A1:C1, 0)", &data),
+            Ok(Value::Number(2.0))
+        );
+        assert_eq!(
+            eval("=MATCH(1, B2:B4, 0)", &data),
+            Err(ErrorKind::Na.into())
+        );
+        assert_eq!(
+            eval("=MATCH(1, A1:C4, 0)", &data),
+            Err(ErrorKind::Na.into())
+        );
+        assert_eq!(
+            eval("=MATCH(30, C2:C4, -1)", &data),
+            Err(ErrorKind::Na.into())
+        );
+        assert_eq!(eval("=MATCH(2, C2:C4)", &data), Ok(Value::Number(2.0)));
+        assert_eq!(
+            eval("=INDEX(B2:B4, MATCH(\"cherry\", A2:A4, 0))", &data),
+            Ok(Value::Number(3.0))
+        );
+        assert_eq!(eval("=INDEX(A1:C4, 2, 3)", &data), Ok(Value::Number(10.0)));
+        assert_eq!(
+            eval("=INDEX(A1:C1, 2)", &data),
+            Ok(Value::Text("Price".to_string()))
+        );
+        assert_eq!(
+            eval("=SUM(INDEX(A1:C4, 0, 3))", &data),
+            Ok(Value::Number(35.0))
+        );
+        assert_eq!(
+            eval("=INDEX(A1:C4, 5, 1)", &data),
+            Err(ErrorKind::Ref.into())
+        );
+    }
+
+    #[test]
+    fn test_xlookup() {
+        let data = prices();
+        let cases = [
+            (
+                "=XLOOKUP(\"banana\", A2:A4, B2:B4)",
+                Ok(Value::Number(0.25)),
+            ),
+            (
+                "=XLOOKUP(\"kiwi\", A2:A4, B2:B4)",
+                Err(ErrorKind::Na.into()),
+            ),
+            (
+                "=XLOOKUP(\"kiwi\", A2:A4, B2:B4, \"none\")",
+                Ok(Value::Text("none".to_string())),
+            ),
+            ("=XLOOKUP(\"Stock\", A1:C1, A3:C3)", Ok(Value::Number(0.0))),
+            (
+                "=XLOOKUP(1, C2:C4, A2:A4, , 1)",
+                Ok(Value::Text("apple".to_string())),
+            ),
+            (
+                "=XLOOKUP(1, C2:C4, A2:A4, , -1)",
+                Ok(Value::Text("banana".to_string())),
+            ),
+            (
+                "=XLOOKUP(\"*e*\", A2:A4, A2:A4, , 2, -1)",
+                Ok(Value::Text("cherry".to_string())),
+            ),
+            (
+                "=XLOOKUP(\"apple\", A2:A4, B2:B3)",
+                Err(ErrorKind::Value.into()),
+            ),
+        ];
+        for (input, expected) in cases.iter() {
+            assert_eq!(&eval(input, &data), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_lookup_dependencies() {
+        let mut data = prices();
+        data.insert(
+            CellPos::new(4, 0),
+            "=VLOOKUP(\"cherry\", A2:C4, 2, FALSE)".to_string(),
+        );
+        data.insert(CellPos::new(1, 3), "3.5".to_string());
+        assert_eq!(data.get(CellPos::new(4, 0)), Some(&Value::Number(3.5)));
+
+        data.insert(CellPos::new(0, 3), "date".to_string());
+        assert_eq!(
+            data.get(CellPos::new(4, 0)),
+            Some(&Value::Error(ErrorKind::Na.into()))
+        );
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("a*c", "ABBC"));
+        assert!(wildcard_match("a?c", "abc"));
+        assert!(!wildcard_match("a?c", "ac"));
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("what~?", "what?"));
+        assert!(!wildcard_match("what~?", "whats"));
+    }
+}
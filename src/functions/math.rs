@@ -0,0 +1,193 @@
+use super::{optional, Arity, Coerce::Any, Coerce::Number, Registry};
+use crate::ast::BinOp;
+use crate::decimal::Rounding;
+use crate::parse::{apply_infix, LangError};
+use crate::value::Value;
+
+pub fn register(registry: &mut Registry) {
+    registry.builtin("ADD", Arity::at_least(0), &[Any], add);
+    registry.builtin("SUB", Arity::exactly(2), &[Any], sub);
+    registry.builtin("MUL", Arity::at_least(0), &[Any], mul);
+    registry.builtin("DIV", Arity::exactly(2), &[Any], div);
+    registry.builtin("POW", Arity::exactly(2), &[Any], pow);
+    registry.builtin("ROUND", Arity::range(1, 2), &[Any, Number], round);
+    registry.builtin("ROUNDUP", Arity::range(1, 2), &[Any, Number], roundup);
+    registry.builtin("ROUNDDOWN", Arity::range(1, 2), &[Any, Number], rounddown);
+    registry.builtin("ROUNDBANK", Arity::range(1, 2), &[Any, Number], roundbank);
+}
+
+/// Adds like `+`, so days added to a date give a date.
+pub fn add(args: &[Value]) -> Result<Value, LangError> {
+    fold(args, BinOp::Add, 0.0)
+}
+
+/// Multiplies like `*`, so money stays exact and units multiply.
+pub fn mul(args: &[Value]) -> Result<Value, LangError> {
+    fold(args, BinOp::Mul, 1.0)
+}
+
+/// Subtracts like `-`, so the days between two dates are a number.
+pub fn sub(args: &[Value]) -> Result<Value, LangError> {
+    apply_infix(args[0].clone(), BinOp::Sub, args[1].clone())
+}
+
+pub fn div(args: &[Value]) -> Result<Value, LangError> {
+    apply_infix(args[0].clone(), BinOp::Div, args[1].clone())
+}
+
+pub fn pow(args: &[Value]) -> Result<Value, LangError> {
+    apply_infix(args[0].clone(), BinOp::Pow, args[1].clone())
+}
+
+/// Rounds halves away from zero.
+pub fn round(args: &[Value]) -> Result<Value, LangError> {
+    round_with(args, Rounding::HalfUp)
+}
+
+pub fn roundup(args: &[Value]) -> Result<Value, LangError> {
+    round_with(args, Rounding::Up)
+}
+
+pub fn rounddown(args: &[Value]) -> Result<Value, LangError> {
+    round_with(args, Rounding::Down)
+}
+
+/// Banker's rounding, taking halves to the even neighbour.
+pub fn roundbank(args: &[Value]) -> Result<Value, LangError> {
+    round_with(args, Rounding::HalfEven)
+}
+
+/// Rounds the first argument to `digits` after the point, or before it
+/// when negative. Money is rounded exactly.
+fn round_with(args: &[Value], rounding: Rounding) -> Result<Value, LangError> {
+    let digits = optional(args, 1, 0.0)?.trunc() as i32;
+    if let Value::Decimal(d) = &args[0] {
+        return Ok(Value::Decimal(d.round(digits, rounding)?));
+    }
+
+    let n = args[0].as_number()?;
+    let scale = 10f64.powi(digits);
+    // Keep 15 significant digits so that 2.675 rounds as written, not as
+    // the 2.67499... it is stored as.
+    let scaled: f64 = format!("{:.14e}", n * scale).parse().unwrap();
+    let whole = match rounding {
+        Rounding::HalfUp => scaled.round(),
+        Rounding::Up => scaled.abs().ceil() * scaled.signum(),
+        Rounding::Down => scaled.trunc(),
+        Rounding::HalfEven if (scaled.fract().abs() - 0.5).abs() < f64::EPSILON => {
+            let down = scaled.trunc();
+            if down % 2.0 == 0.0 {
+                down
+            } else {
+                down + scaled.signum()
+            }
+        }
+        Rounding::HalfEven => scaled.round(),
+    };
+    let out = whole / scale;
+    if out.is_finite() {
+        Ok(Value::Number(out))
+    } else {
+        Err(LangError::num("The result is not a finite number"))
+    }
+}
+
+/// Combines the arguments left to right with `op`, starting from `empty`
+/// when there are none.
+fn fold(args: &[Value], op: BinOp, empty: f64) -> Result<Value, LangError> {
+    match args.split_first() {
+        None => Ok(Value::Number(empty)),
+        Some((first, rest)) => rest.iter().try_fold(operand(first)?, |acc, val| {
+            apply_infix(acc, op, val.clone())
+        }),
+    }
+}
+
+/// Keeps the values `apply_infix` does arithmetic on as they are, and
+/// coerces anything else to a number.
+fn operand(val: &Value) -> Result<Value, LangError> {
+    match val {
+        Value::Number(_)
+        | Value::Decimal(_)
+        | Value::Quantity(_, _)
+        | Value::Date(_)
+        | Value::Time(_)
+        | Value::DateTime(_) => Ok(val.clone()),
+        _ => val.as_number().map(Value::Number),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::Data;
+    use crate::parse::parse;
+    use crate::position::CellPos;
+
+    #[test]
+    fn test_rounding() {
+        let data = Data::new();
+        let cases = [
+            ("=ROUND(2.675, 2)", "2.68"),
+            ("=ROUND(-2.5)", "-3"),
+            ("=ROUND(1234.5, -2)", "1200"),
+            ("=ROUNDUP(2.001, 2)", "2.01"),
+            ("=ROUNDUP(-2.001, 2)", "-2.01"),
+            ("=ROUNDDOWN(2.999, 1)", "2.9"),
+            ("=ROUNDBANK(2.5)", "2"),
+            ("=ROUNDBANK(3.5)", "4"),
+            ("=ROUNDBANK(-0.125, 2)", "-0.12"),
+            ("=ROUNDBANK(0.1251, 2)", "0.13"),
+        ];
+        for (input, expected) in cases.iter() {
+            let (val, _) = parse(input, &data).unwrap();
+            assert_eq!(val.to_string(), *expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_money() {
+        let mut data = Data::new();
+        data.insert(CellPos::new(0, 0), "$0.10".to_string());
+        data.insert(CellPos::new(0, 1), "$0.20".to_string());
+        data.insert(CellPos::new(0, 2), "$9,999,999,999,999.99".to_string());
+
+        let cases = [
+            ("=A1 + A2", "0.30"),
+            ("=A1 + A2 = 0.3", "TRUE"),
+            ("=SUM(A1:A2) * 3", "0.90"),
+            ("=SUM(A1:A3)", "10,000,000,000,000.29"),
+            ("=A3 * 1000", "9,999,999,999,999,990.00"),
+            ("=A1 / 3", "0.0333"),
+            ("=-A1 + 15%", "0.05"),
+            ("=ROUNDBANK(A1 * 1.25, 2)", "0.12"),
+            ("=ROUND(A1 * 1.25, 2)", "0.13"),
+            ("=add A1", "0.10"),
+            ("=mul A1 3", "0.30"),
+            ("=mul A1 A2", "0.02"),
+            ("=div A1 3", "0.0333"),
+            ("=div 1 A1", "10.00"),
+        ];
+        for (input, expected) in cases.iter() {
+            let (val, _) = parse(input, &data).unwrap();
+            assert_eq!(val.to_string(), *expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_quantities() {
+        let mut data = Data::new();
+        data.insert(CellPos::new(0, 1), "5 kg".to_string());
+
+        let cases = [
+            ("=add A2", "5 kg"),
+            ("=mul 2 A2", "10 kg"),
+            ("=mul A2 A2", "25 kg^2"),
+            ("=div A2 2", "2.5 kg"),
+            ("=pow A2 2", "25 kg^2"),
+        ];
+        for (input, expected) in cases.iter() {
+            let (val, _) = parse(input, &data).unwrap();
+            assert_eq!(val.to_string(), *expected, "{}", input);
+        }
+    }
+}
@@ -0,0 +1,403 @@
+use crate::decimal::Decimal;
+use crate::parse::{Args, LangError};
+use crate::unit::{Dimension, Unit};
+use crate::value::Value;
+use log::debug;
+use std::collections::HashMap;
+
+mod aggregate;
+mod array;
+mod date;
+mod finance;
+mod lambda;
+mod logic;
+mod lookup;
+mod math;
+mod pattern;
+mod stats;
+mod text;
+mod units;
+
+/// The number of arguments a function accepts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Arity {
+    pub min: usize,
+    pub max: Option<usize>,
+}
+
+impl Arity {
+    pub fn exactly(n: usize) -> Self {
+        Self::range(n, n)
+    }
+
+    pub fn range(min: usize, max: usize) -> Self {
+        Self {
+            min,
+            max: Some(max),
+        }
+    }
+
+    pub fn at_least(min: usize) -> Self {
+        Self { min, max: None }
+    }
+
+    pub fn contains(&self, n: usize) -> bool {
+        n >= self.min && self.max.is_none_or(|max| n <= max)
+    }
+}
+
+/// How an argument is converted before a function sees it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Coerce {
+    Any,
+    Number,
+    Text,
+    Bool,
+    Date,
+}
+
+impl Coerce {
+    pub fn apply(self, val: Value) -> Result<Value, LangError> {
+        match self {
+            Coerce::Any => Ok(val),
+            Coerce::Number => val.as_number().map(Value::Number),
+            Coerce::Text => val.as_text().map(Value::Text),
+            Coerce::Bool => val.as_bool().map(Value::Bool),
+            Coerce::Date => val.as_date().map(Value::Date),
+        }
+    }
+}
+
+/// A function callable from formulas. Arguments are only evaluated when
+/// `evaluate` asks for them, which lets functions like IF skip a branch.
+pub trait Function {
+    fn name(&self) -> &str;
+
+    fn arity(&self) -> Arity;
+
+    /// The conversion applied to the argument at `index` when it is evaluated.
+    fn coerce(&self, _index: usize) -> Coerce {
+        Coerce::Any
+    }
+
+    fn evaluate(&self, args: &mut Args) -> Result<Value, LangError>;
+}
+
+/// A function that evaluates all of its arguments up front.
+pub struct Builtin {
+    name: &'static str,
+    arity: Arity,
+    coerce: &'static [Coerce],
+    func: fn(&[Value]) -> Result<Value, LangError>,
+}
+
+impl Builtin {
+    /// `coerce` gives the conversion of each argument, with the last entry
+    /// repeating for any further arguments.
+    pub fn new(
+        name: &'static str,
+        arity: Arity,
+        coerce: &'static [Coerce],
+        func: fn(&[Value]) -> Result<Value, LangError>,
+    ) -> Self {
+        Self {
+            name,
+            arity,
+            coerce,
+            func,
+        }
+    }
+}
+
+impl Function for Builtin {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn arity(&self) -> Arity {
+        self.arity
+    }
+
+    fn coerce(&self, index: usize) -> Coerce {
+        let last = self.coerce.len().saturating_sub(1);
+        self.coerce
+            .get(index.min(last))
+            .cloned()
+            .unwrap_or(Coerce::Any)
+    }
+
+    fn evaluate(&self, args: &mut Args) -> Result<Value, LangError> {
+        (self.func)(&args.values()?)
+    }
+}
+
+/// The functions known to a workbook, looked up by case-insensitive name.
+pub struct Registry {
+    functions: HashMap<String, Box<dyn Function>>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let mut registry = Self {
+            functions: HashMap::new(),
+        };
+        math::register(&mut registry);
+        aggregate::register(&mut registry);
+        text::register(&mut registry);
+        pattern::register(&mut registry);
+        units::register(&mut registry);
+        logic::register(&mut registry);
+        date::register(&mut registry);
+        lookup::register(&mut registry);
+        stats::register(&mut registry);
+        finance::register(&mut registry);
+        array::register(&mut registry);
+        lambda::register(&mut registry);
+        registry
+    }
+}
+
+impl Registry {
+    /// Adds a function, replacing any existing one with the same name.
+    pub fn register<F: Function + 'static>(&mut self, function: F) {
+        let name = function.name().to_ascii_uppercase();
+        self.functions.insert(name, Box::new(function));
+    }
+
+    /// Adds a [`Builtin`] evaluating all of its arguments up front.
+    pub fn builtin(
+        &mut self,
+        name: &'static str,
+        arity: Arity,
+        coerce: &'static [Coerce],
+        func: fn(&[Value]) -> Result<Value, LangError>,
+    ) {
+        self.register(Builtin::new(name, arity, coerce, func));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Function> {
+        let function = self.functions.get(&name.to_ascii_uppercase());
+        if function.is_none() {
+            debug!("Invalid Function: {}", name);
+        }
+        function.map(|f| f.as_ref())
+    }
+}
+
+/// A numeric argument that may be left out or blank.
+pub fn optional(args: &[Value], index: usize, default: f64) -> Result<f64, LangError> {
+    match args.get(index) {
+        None | Some(Value::Empty) => Ok(default),
+        Some(val) => val.as_number(),
+    }
+}
+
+/// The rows of a range, with anything else treated as a single cell.
+pub fn rows(val: &Value) -> Vec<Vec<Value>> {
+    match val {
+        Value::Array(rows) => rows.clone(),
+        v => vec![vec![v.clone()]],
+    }
+}
+
+/// Collects the truth values in `args` for the logical functions. Inside a
+/// range text and blanks are skipped, and it is an error if nothing is left.
+pub fn booleans(args: &[Value]) -> Result<Vec<bool>, LangError> {
+    let mut out = Vec::new();
+    for arg in args {
+        match arg {
+            Value::Array(_) => {
+                for val in arg.iter() {
+                    match val {
+                        Value::Bool(_) | Value::Number(_) | Value::Error(_) => {
+                            out.push(val.as_bool()?)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Value::Empty => {}
+            val => out.push(val.as_bool()?),
+        }
+    }
+
+    if out.is_empty() {
+        return Err(LangError::value("There are no logical values"));
+    }
+    Ok(out)
+}
+
+/// Collects the numbers in `args` the way aggregate functions do. Inside a
+/// range only numbers and dates count, while blanks, text and booleans are
+/// skipped.
+/// Arguments given directly are coerced, apart from blank cells.
+pub fn numbers(args: &[Value]) -> Result<Vec<f64>, LangError> {
+    let mut out = Vec::new();
+    for arg in args {
+        match arg {
+            Value::Array(_) => {
+                for val in arg.iter() {
+                    match val {
+                        v if v.is_numeric() => out.push(v.as_number()?),
+                        Value::Error(e) => return Err(e.clone()),
+                        _ => {}
+                    }
+                }
+            }
+            Value::Empty => {}
+            val => out.push(val.as_number()?),
+        }
+    }
+    Ok(out)
+}
+
+/// Like [`numbers`], but exact when `args` hold money. Gives `None` when
+/// there is no money among them.
+pub fn decimals(args: &[Value]) -> Option<Result<Vec<Decimal>, LangError>> {
+    let has_money = args
+        .iter()
+        .flat_map(|arg| arg.iter())
+        .any(|v| matches!(v, Value::Decimal(_)));
+    if !has_money {
+        return None;
+    }
+
+    let mut out = Vec::new();
+    for arg in args {
+        let vals: Box<dyn Iterator<Item = &Value>> = match arg {
+            Value::Array(_) => Box::new(
+                arg.iter()
+                    .filter(|v| v.is_numeric() || matches!(v, Value::Error(_))),
+            ),
+            Value::Empty => continue,
+            val => Box::new(std::iter::once(val)),
+        };
+        for val in vals {
+            match val.as_decimal() {
+                Ok(d) => out.push(d),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+    Some(Ok(out))
+}
+
+/// Like [`numbers`], but for quantities, which are converted to the unit of
+/// the first one. Gives `None` when there are no quantities among `args`.
+pub fn quantities(args: &[Value]) -> Option<Result<(Vec<f64>, Unit), LangError>> {
+    let unit = args
+        .iter()
+        .flat_map(|arg| arg.iter())
+        .find_map(|v| match v {
+            Value::Quantity(_, unit) => Some(unit.clone()),
+            _ => None,
+        })?;
+
+    let mut out = Vec::new();
+    for arg in args {
+        let vals: Box<dyn Iterator<Item = &Value>> = match arg {
+            Value::Array(_) => Box::new(
+                arg.iter()
+                    .filter(|v| v.is_numeric() || matches!(v, Value::Error(_))),
+            ),
+            Value::Empty => continue,
+            val => Box::new(std::iter::once(val)),
+        };
+        for val in vals {
+            let n = match val {
+                Value::Quantity(n, other) => other.convert(*n, &unit),
+                Value::Error(e) => Err(e.clone()),
+                _ => Err(Unit::mismatch(unit.dimension, Dimension::NONE)),
+            };
+            match n {
+                Ok(n) => out.push(n),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+    Some(Ok((out, unit)))
+}
+
+/// Helpers shared by the tests of the function modules.
+#[cfg(test)]
+pub mod testing {
+    use crate::data::Data;
+    use crate::parse::{parse, LangError};
+    use crate::position::CellPos;
+    use crate::value::Value;
+
+    /// A sheet holding `rows` from A1.
+    pub fn sheet(rows: &[&[&str]]) -> Data {
+        let mut data = Data::new();
+        for (y, row) in rows.iter().enumerate() {
+            for (x, val) in row.iter().enumerate() {
+                data.insert(CellPos::new(x, y), val.to_string());
+            }
+        }
+        data
+    }
+
+    pub fn eval(input: &str, data: &Data) -> Result<Value, LangError> {
+        parse(input, data).map(|(val, _)| val)
+    }
+
+    /// Checks that a formula gives a number within `tolerance` of `expected`.
+    pub fn assert_close(input: &str, expected: f64, tolerance: f64, data: &Data) {
+        match eval(input, data) {
+            Ok(Value::Number(n)) => assert!(
+                (n - expected).abs() < tolerance,
+                "{} = {}, not {}",
+                input,
+                n,
+                expected
+            ),
+            out => panic!("{} gave {:?}", input, out),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Data;
+    use crate::parse::{parse, ErrorKind};
+
+    struct Double;
+
+    impl Function for Double {
+        fn name(&self) -> &str {
+            "double"
+        }
+
+        fn arity(&self) -> Arity {
+            Arity::exactly(1)
+        }
+
+        fn coerce(&self, _index: usize) -> Coerce {
+            Coerce::Number
+        }
+
+        fn evaluate(&self, args: &mut Args) -> Result<Value, LangError> {
+            Ok(Value::Number(args.eval(0)?.as_number()? * 2.0))
+        }
+    }
+
+    #[test]
+    fn test_register_function() {
+        let mut data = Data::new();
+        data.register_function(Double);
+
+        let (val, _) = parse("=DOUBLE(21)", &data).unwrap();
+        assert_eq!(val, Value::Number(42.0));
+        assert_eq!(parse("=double \"x\"", &data), Err(ErrorKind::Value.into()));
+        assert_eq!(parse("=DOUBLE(1, 2)", &data), Err(ErrorKind::Value.into()));
+    }
+
+    #[test]
+    fn test_builtin_arity() {
+        let data = Data::new();
+        assert_eq!(parse("=LEN()", &data), Err(ErrorKind::Value.into()));
+        assert_eq!(parse("=sub 1 2 3", &data), Err(ErrorKind::Value.into()));
+        assert_eq!(parse("=NOPE(1)", &data), Err(ErrorKind::Name.into()));
+    }
+}
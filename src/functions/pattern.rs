@@ -0,0 +1,201 @@
+use super::{Arity, Coerce, Function, Registry};
+use crate::parse::{Args, LangError};
+use crate::value::Value;
+use regex::Regex;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Patterns kept before the least recently used is dropped, so that
+/// formulas building a new pattern on every recalculation do not grow the
+/// cache forever.
+const CACHE_SIZE: usize = 256;
+
+pub fn register(registry: &mut Registry) {
+    let patterns = Rc::new(Patterns::default());
+    for kind in [Kind::Match, Kind::Extract, Kind::Replace].iter() {
+        registry.register(RegexFunction {
+            kind: *kind,
+            patterns: patterns.clone(),
+        });
+    }
+}
+
+/// A compiled pattern, or why it does not compile, and when it was last
+/// used.
+type Entry = (Result<Regex, LangError>, u64);
+
+/// Compiled patterns, shared by the regular expression functions so a
+/// formula is only compiled again when its pattern changes.
+#[derive(Default)]
+struct Patterns {
+    cache: RefCell<HashMap<String, Entry>>,
+    clock: Cell<u64>,
+}
+
+impl Patterns {
+    fn get(&self, pattern: &str) -> Result<Regex, LangError> {
+        let now = self.clock.get() + 1;
+        self.clock.set(now);
+        let mut cache = self.cache.borrow_mut();
+        if let Some((regex, used)) = cache.get_mut(pattern) {
+            *used = now;
+            return regex.clone();
+        }
+
+        if cache.len() >= CACHE_SIZE {
+            let oldest = cache
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(pattern, _)| pattern.clone());
+            if let Some(oldest) = oldest {
+                cache.remove(&oldest);
+            }
+        }
+        let regex = Regex::new(pattern).map_err(|e| {
+            // Syntax errors span several lines, ending with the reason.
+            let e = e.to_string();
+            let reason = e.lines().last().unwrap_or_default();
+            let reason = reason.trim_start_matches("error: ");
+            LangError::value(format!("Invalid pattern {}: {}", pattern, reason))
+        });
+        cache.insert(pattern.to_string(), (regex.clone(), now));
+        regex
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Match,
+    Extract,
+    Replace,
+}
+
+/// `REGEXMATCH(text, pattern)`, `REGEXEXTRACT(text, pattern)` and
+/// `REGEXREPLACE(text, pattern, replacement)`.
+struct RegexFunction {
+    kind: Kind,
+    patterns: Rc<Patterns>,
+}
+
+impl Function for RegexFunction {
+    fn name(&self) -> &str {
+        match self.kind {
+            Kind::Match => "REGEXMATCH",
+            Kind::Extract => "REGEXEXTRACT",
+            Kind::Replace => "REGEXREPLACE",
+        }
+    }
+
+    fn arity(&self) -> Arity {
+        match self.kind {
+            Kind::Replace => Arity::exactly(3),
+            _ => Arity::exactly(2),
+        }
+    }
+
+    fn coerce(&self, _index: usize) -> Coerce {
+        Coerce::Text
+    }
+
+    fn evaluate(&self, args: &mut Args) -> Result<Value, LangError> {
+        let args = args.values()?;
+        let text = args[0].as_text()?;
+        let regex = self.patterns.get(&args[1].as_text()?)?;
+        match self.kind {
+            Kind::Match => Ok(Value::Bool(regex.is_match(&text))),
+            Kind::Extract => extract(&regex, &text),
+            Kind::Replace => {
+                let replacement = args[2].as_text()?;
+                Ok(Value::Text(
+                    regex.replace_all(&text, replacement.as_str()).into_owned(),
+                ))
+            }
+        }
+    }
+}
+
+/// The first match, or its capture groups in a row when the pattern has
+/// any. Groups that took no part in the match are blank.
+fn extract(regex: &Regex, text: &str) -> Result<Value, LangError> {
+    let captures = regex
+        .captures(text)
+        .ok_or_else(|| LangError::na(format!("{} does not match {}", text, regex)))?;
+    if captures.len() == 1 {
+        return Ok(Value::Text(captures[0].to_string()));
+    }
+
+    let groups = captures
+        .iter()
+        .skip(1)
+        .map(|group| match group {
+            Some(m) => Value::Text(m.as_str().to_string()),
+            None => Value::Empty,
+        })
+        .collect();
+    Ok(Value::Array(vec![groups]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Patterns, CACHE_SIZE};
+    use crate::data::Data;
+    use crate::parse::{parse, ErrorKind};
+    use crate::position::CellPos;
+    use crate::value::Value;
+
+    #[test]
+    fn test_regex() {
+        let mut data = Data::new();
+        data.insert(CellPos::new(0, 0), "2020-03-01 ERROR disk full".to_string());
+
+        let cases = [
+            ("=REGEXMATCH(A1, \"ERROR|WARN\")", Value::Bool(true)),
+            ("=REGEXMATCH(A1, \"^INFO\")", Value::Bool(false)),
+            (
+                "=REGEXEXTRACT(A1, \"[A-Z]+\")",
+                Value::Text("ERROR".to_string()),
+            ),
+            (
+                "=REGEXREPLACE(A1, \"(\\d+)-(\\d+)-(\\d+)\", \"$3/$2/$1\")",
+                Value::Text("01/03/2020 ERROR disk full".to_string()),
+            ),
+        ];
+        for (input, expected) in cases.iter() {
+            let (val, _) = parse(input, &data).unwrap();
+            assert_eq!(&val, expected, "{}", input);
+        }
+
+        let (val, _) = parse("=REGEXEXTRACT(A1, \"(\\w+) (\\w+)$\")", &data).unwrap();
+        assert_eq!(
+            val,
+            Value::Array(vec![vec![
+                Value::Text("disk".to_string()),
+                Value::Text("full".to_string())
+            ]])
+        );
+
+        assert_eq!(
+            parse("=REGEXEXTRACT(A1, \"INFO\")", &data),
+            Err(ErrorKind::Na.into())
+        );
+        let e = parse("=REGEXMATCH(A1, \"(ERROR\")", &data).unwrap_err();
+        assert_eq!(e, ErrorKind::Value.into());
+        assert_eq!(e.reason, "Invalid pattern (ERROR: unclosed group");
+    }
+
+    #[test]
+    fn test_pattern_cache() {
+        let patterns = Patterns::default();
+        for i in 0..CACHE_SIZE {
+            patterns.get(&format!("a{{{}}}", i)).unwrap();
+        }
+        patterns.get("a{0}").unwrap();
+        patterns.get("b").unwrap();
+
+        let cache = patterns.cache.borrow();
+        assert_eq!(cache.len(), CACHE_SIZE);
+        assert!(cache.contains_key("a{0}"));
+        assert!(!cache.contains_key("a{1}"));
+    }
+}
@@ -0,0 +1,297 @@
+use super::{numbers, Arity, Coerce::*, Registry};
+use crate::parse::LangError;
+use crate::value::Value;
+use std::cmp::Ordering;
+
+pub fn register(registry: &mut Registry) {
+    registry.builtin("MEDIAN", Arity::at_least(1), &[], median);
+    registry.builtin("MODE", Arity::at_least(1), &[], mode);
+    registry.builtin("MODE.SNGL", Arity::at_least(1), &[], mode);
+    registry.builtin("STDEV", Arity::at_least(1), &[], stdev_s);
+    registry.builtin("STDEV.S", Arity::at_least(1), &[], stdev_s);
+    registry.builtin("STDEV.P", Arity::at_least(1), &[], stdev_p);
+    registry.builtin("VAR", Arity::at_least(1), &[], var_s);
+    registry.builtin("VAR.S", Arity::at_least(1), &[], var_s);
+    registry.builtin("VAR.P", Arity::at_least(1), &[], var_p);
+    registry.builtin("PERCENTILE", Arity::exactly(2), &[Any, Number], percentile);
+    registry.builtin(
+        "PERCENTILE.INC",
+        Arity::exactly(2),
+        &[Any, Number],
+        percentile,
+    );
+    registry.builtin("QUARTILE", Arity::exactly(2), &[Any, Number], quartile);
+    registry.builtin("QUARTILE.INC", Arity::exactly(2), &[Any, Number], quartile);
+    registry.builtin("RANK", Arity::range(2, 3), &[Number, Any, Number], rank);
+    registry.builtin("RANK.EQ", Arity::range(2, 3), &[Number, Any, Number], rank);
+    registry.builtin("CORREL", Arity::exactly(2), &[], correl);
+    registry.builtin("COVAR", Arity::exactly(2), &[], covariance_p);
+    registry.builtin("COVARIANCE.P", Arity::exactly(2), &[], covariance_p);
+    registry.builtin("COVARIANCE.S", Arity::exactly(2), &[], covariance_s);
+    registry.builtin("LARGE", Arity::exactly(2), &[Any, Number], large);
+    registry.builtin("SMALL", Arity::exactly(2), &[Any, Number], small);
+}
+
+pub fn median(args: &[Value]) -> Result<Value, LangError> {
+    percentile_of(sorted(args)?, 0.5).map(Value::Number)
+}
+
+/// The most common value, taking the first to appear when there is a tie.
+/// It is an error if no value appears twice.
+pub fn mode(args: &[Value]) -> Result<Value, LangError> {
+    let vals = numbers(args)?;
+    let mut best: Option<(f64, usize)> = None;
+    for (i, n) in vals.iter().enumerate() {
+        let count = vals[i..].iter().filter(|m| *m == n).count();
+        if count > 1 && best.is_none_or(|(_, most)| count > most) {
+            best = Some((*n, count));
+        }
+    }
+    best.map(|(n, _)| Value::Number(n))
+        .ok_or_else(|| LangError::na("No value is repeated"))
+}
+
+pub fn stdev_s(args: &[Value]) -> Result<Value, LangError> {
+    variance(&numbers(args)?, 1).map(|v| Value::Number(v.sqrt()))
+}
+
+pub fn stdev_p(args: &[Value]) -> Result<Value, LangError> {
+    variance(&numbers(args)?, 0).map(|v| Value::Number(v.sqrt()))
+}
+
+pub fn var_s(args: &[Value]) -> Result<Value, LangError> {
+    variance(&numbers(args)?, 1).map(Value::Number)
+}
+
+pub fn var_p(args: &[Value]) -> Result<Value, LangError> {
+    variance(&numbers(args)?, 0).map(Value::Number)
+}
+
+/// `PERCENTILE(range, k)` interpolates between the values around the
+/// fraction `k` of the way through the sorted range.
+pub fn percentile(args: &[Value]) -> Result<Value, LangError> {
+    let k = args[1].as_number()?;
+    percentile_of(sorted(&args[..1])?, k).map(Value::Number)
+}
+
+/// `QUARTILE(range, q)` is the minimum, quartiles and maximum for q from 0
+/// to 4.
+pub fn quartile(args: &[Value]) -> Result<Value, LangError> {
+    let q = args[1].as_number()?.trunc();
+    if !(0.0..=4.0).contains(&q) {
+        return Err(LangError::num("The quartile must be from 0 to 4"));
+    }
+    percentile_of(sorted(&args[..1])?, q / 4.0).map(Value::Number)
+}
+
+/// `RANK(number, range, [ascending])` gives the position of the number in
+/// the range sorted from largest to smallest, or smallest to largest when
+/// the last argument is not 0. Equal values share a rank.
+pub fn rank(args: &[Value]) -> Result<Value, LangError> {
+    let n = args[0].as_number()?;
+    let vals = numbers(&args[1..2])?;
+    if !vals.contains(&n) {
+        return Err(LangError::na(format!("{} is not in the range", n)));
+    }
+
+    let ascending = match args.get(2) {
+        Some(order) => order.as_number()? != 0.0,
+        None => false,
+    };
+    let ahead = vals
+        .iter()
+        .filter(|m| if ascending { **m < n } else { **m > n })
+        .count();
+    Ok(Value::Number((ahead + 1) as f64))
+}
+
+/// The Pearson correlation coefficient of two ranges of the same size.
+pub fn correl(args: &[Value]) -> Result<Value, LangError> {
+    let (xs, ys) = pairs(&args[0], &args[1])?;
+    let (vx, vy) = (variance(&xs, 0)?, variance(&ys, 0)?);
+    if vx == 0.0 || vy == 0.0 {
+        return Err(LangError::div("The values do not vary"));
+    }
+    Ok(Value::Number(covariance(&xs, &ys, 0)? / (vx * vy).sqrt()))
+}
+
+pub fn covariance_p(args: &[Value]) -> Result<Value, LangError> {
+    let (xs, ys) = pairs(&args[0], &args[1])?;
+    covariance(&xs, &ys, 0).map(Value::Number)
+}
+
+pub fn covariance_s(args: &[Value]) -> Result<Value, LangError> {
+    let (xs, ys) = pairs(&args[0], &args[1])?;
+    covariance(&xs, &ys, 1).map(Value::Number)
+}
+
+/// `LARGE(range, k)` is the k-th largest value.
+pub fn large(args: &[Value]) -> Result<Value, LangError> {
+    let mut vals = sorted(&args[..1])?;
+    vals.reverse();
+    nth(&vals, &args[1])
+}
+
+/// `SMALL(range, k)` is the k-th smallest value.
+pub fn small(args: &[Value]) -> Result<Value, LangError> {
+    nth(&sorted(&args[..1])?, &args[1])
+}
+
+fn sorted(args: &[Value]) -> Result<Vec<f64>, LangError> {
+    let mut vals = numbers(args)?;
+    vals.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    Ok(vals)
+}
+
+fn nth(sorted: &[f64], k: &Value) -> Result<Value, LangError> {
+    let k = k.as_number()?.ceil();
+    if k < 1.0 || k > sorted.len() as f64 {
+        return Err(LangError::num(format!("There is no value number {}", k)));
+    }
+    Ok(Value::Number(sorted[k as usize - 1]))
+}
+
+/// The inclusive percentile `k` of sorted values.
+fn percentile_of(sorted: Vec<f64>, k: f64) -> Result<f64, LangError> {
+    if sorted.is_empty() {
+        return Err(LangError::num("There are no numbers"));
+    }
+    if !(0.0..=1.0).contains(&k) {
+        return Err(LangError::num("The percentile must be from 0 to 1"));
+    }
+    let rank = k * (sorted.len() - 1) as f64;
+    let (low, fraction) = (rank.floor() as usize, rank.fract());
+    let high = sorted.get(low + 1).unwrap_or(&sorted[low]);
+    Ok(sorted[low] + fraction * (high - sorted[low]))
+}
+
+fn mean(vals: &[f64]) -> f64 {
+    vals.iter().sum::<f64>() / vals.len() as f64
+}
+
+/// The variance of `vals`, dividing by their number less `ddof`: 1 for a
+/// sample and 0 for a whole population.
+fn variance(vals: &[f64], ddof: usize) -> Result<f64, LangError> {
+    covariance(vals, vals, ddof)
+}
+
+fn covariance(xs: &[f64], ys: &[f64], ddof: usize) -> Result<f64, LangError> {
+    if xs.len() <= ddof {
+        return Err(LangError::div("There are too few numbers"));
+    }
+    let (mx, my) = (mean(xs), mean(ys));
+    let sum: f64 = xs.iter().zip(ys).map(|(x, y)| (x - mx) * (y - my)).sum();
+    Ok(sum / (xs.len() - ddof) as f64)
+}
+
+/// The numbers at the same positions in two ranges, skipping positions
+/// where either is not a number.
+fn pairs(a: &Value, b: &Value) -> Result<(Vec<f64>, Vec<f64>), LangError> {
+    let (a, b): (Vec<_>, Vec<_>) = (a.iter().collect(), b.iter().collect());
+    if a.len() != b.len() {
+        return Err(LangError::na("The ranges are different sizes"));
+    }
+
+    let mut xs = Vec::new();
+    let mut ys = Vec::new();
+    for (x, y) in a.into_iter().zip(b) {
+        for val in [x, y] {
+            if let Value::Error(e) = val {
+                return Err(e.clone());
+            }
+        }
+        if x.is_numeric() && y.is_numeric() {
+            xs.push(x.as_number()?);
+            ys.push(y.as_number()?);
+        }
+    }
+    Ok((xs, ys))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::Data;
+    use crate::functions::testing::assert_close;
+    use crate::parse::{parse, ErrorKind};
+    use crate::position::CellPos;
+
+    /// A1:A8 holds 2, 4, 4, 4, 5, 5, 7, 9 and B1:C5 holds x = 1..5 and
+    /// y = 2, 4, 5, 4, 5.
+    fn sample() -> Data {
+        let mut data = Data::new();
+        for (y, n) in [2, 4, 4, 4, 5, 5, 7, 9].iter().enumerate() {
+            data.insert(CellPos::new(0, y), n.to_string());
+        }
+        for (y, n) in [2, 4, 5, 4, 5].iter().enumerate() {
+            data.insert(CellPos::new(1, y), (y + 1).to_string());
+            data.insert(CellPos::new(2, y), n.to_string());
+        }
+        data
+    }
+
+    #[test]
+    fn test_central_tendency() {
+        let data = sample();
+        assert_close("=MEDIAN(A1:A8)", 4.5, 1e-9, &data);
+        assert_close("=MEDIAN(A1:A7)", 4.0, 1e-9, &data);
+        assert_close("=MODE(A1:A8)", 4.0, 1e-9, &data);
+        assert_close("=MODE(5, 1, 1, 5)", 5.0, 1e-9, &data);
+        assert_eq!(parse("=MODE(B1:B5)", &data), Err(ErrorKind::Na.into()));
+        assert_eq!(parse("=MEDIAN(D1:D5)", &data), Err(ErrorKind::Num.into()));
+    }
+
+    #[test]
+    fn test_spread() {
+        let data = sample();
+        assert_close("=STDEV.P(A1:A8)", 2.0, 1e-9, &data);
+        assert_close("=VAR.P(A1:A8)", 4.0, 1e-9, &data);
+        assert_close("=STDEV.S(A1:A8)", (32.0f64 / 7.0).sqrt(), 1e-9, &data);
+        assert_close("=VAR(A1:A8)", 32.0 / 7.0, 1e-9, &data);
+        assert_eq!(parse("=STDEV.S(5)", &data), Err(ErrorKind::Div.into()));
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let data = sample();
+        assert_close("=PERCENTILE(A1:A8, 0.25)", 4.0, 1e-9, &data);
+        assert_close("=PERCENTILE(A1:A8, 0.9)", 7.6, 1e-9, &data);
+        assert_close("=QUARTILE(A1:A8, 3)", 5.5, 1e-9, &data);
+        assert_close("=QUARTILE(A1:A8, 4)", 9.0, 1e-9, &data);
+        assert_eq!(
+            parse("=PERCENTILE(A1:A8, 1.5)", &data),
+            Err(ErrorKind::Num.into())
+        );
+        assert_eq!(
+            parse("=QUARTILE(A1:A8, 5)", &data),
+            Err(ErrorKind::Num.into())
+        );
+    }
+
+    #[test]
+    fn test_ranking() {
+        let data = sample();
+        assert_close("=RANK(5, A1:A8)", 3.0, 1e-9, &data);
+        assert_close("=RANK(5, A1:A8, 1)", 5.0, 1e-9, &data);
+        assert_close("=RANK(4, A1:A8)", 5.0, 1e-9, &data);
+        assert_close("=LARGE(A1:A8, 2)", 7.0, 1e-9, &data);
+        assert_close("=SMALL(A1:A8, 3)", 4.0, 1e-9, &data);
+        assert_eq!(parse("=RANK(6, A1:A8)", &data), Err(ErrorKind::Na.into()));
+        assert_eq!(parse("=LARGE(A1:A8, 9)", &data), Err(ErrorKind::Num.into()));
+    }
+
+    #[test]
+    fn test_correlation() {
+        let data = sample();
+        assert_close("=CORREL(B1:B5, C1:C5)", 0.7745966692414834, 1e-9, &data);
+        assert_close("=COVARIANCE.P(B1:B5, C1:C5)", 1.2, 1e-9, &data);
+        assert_close("=COVARIANCE.S(B1:B5, C1:C5)", 1.5, 1e-9, &data);
+        assert_eq!(
+            parse("=CORREL(B1:B5, C1:C4)", &data),
+            Err(ErrorKind::Na.into())
+        );
+        assert_eq!(
+            parse("=CORREL(B1:B5, D1:D5)", &data),
+            Err(ErrorKind::Div.into())
+        );
+    }
+}
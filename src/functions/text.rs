@@ -0,0 +1,289 @@
+use super::{Arity, Coerce::*, Registry};
+use crate::parse::LangError;
+use crate::value::Value;
+
+pub fn register(registry: &mut Registry) {
+    registry.builtin("CONCAT", Arity::at_least(1), &[], concat);
+    registry.builtin("CONCATENATE", Arity::at_least(1), &[], concat);
+    registry.builtin("LEN", Arity::exactly(1), &[Text], len);
+    registry.builtin("UPPER", Arity::exactly(1), &[Text], upper);
+    registry.builtin("LOWER", Arity::exactly(1), &[Text], lower);
+    registry.builtin("TRIM", Arity::exactly(1), &[Text], trim);
+    registry.builtin("LEFT", Arity::range(1, 2), &[Text, Number], left);
+    registry.builtin("RIGHT", Arity::range(1, 2), &[Text, Number], right);
+    registry.builtin("MID", Arity::exactly(3), &[Text, Number], mid);
+    registry.builtin("FIND", Arity::range(2, 3), &[Text, Text, Number], find);
+    registry.builtin(
+        "SUBSTITUTE",
+        Arity::range(3, 4),
+        &[Text, Text, Text, Number],
+        substitute,
+    );
+    registry.builtin("TEXT", Arity::exactly(2), &[Any, Text], text);
+    registry.builtin("VALUE", Arity::exactly(1), &[Number], value);
+}
+
+pub fn concat(args: &[Value]) -> Result<Value, LangError> {
+    let mut out = String::new();
+    for val in args.iter().flat_map(|arg| arg.iter()) {
+        out.push_str(&val.as_text()?);
+    }
+    Ok(Value::Text(out))
+}
+
+pub fn len(args: &[Value]) -> Result<Value, LangError> {
+    Ok(Value::Number(args[0].as_text()?.chars().count() as f64))
+}
+
+pub fn upper(args: &[Value]) -> Result<Value, LangError> {
+    Ok(Value::Text(args[0].as_text()?.to_uppercase()))
+}
+
+pub fn lower(args: &[Value]) -> Result<Value, LangError> {
+    Ok(Value::Text(args[0].as_text()?.to_lowercase()))
+}
+
+/// Removes leading and trailing spaces and collapses runs of inner spaces.
+pub fn trim(args: &[Value]) -> Result<Value, LangError> {
+    let text = args[0].as_text()?;
+    Ok(Value::Text(
+        text.split_whitespace().collect::<Vec<_>>().join(" "),
+    ))
+}
+
+pub fn left(args: &[Value]) -> Result<Value, LangError> {
+    let text = args[0].as_text()?;
+    let n = count_arg(args.get(1), 1)?;
+    Ok(Value::Text(text.chars().take(n).collect()))
+}
+
+pub fn right(args: &[Value]) -> Result<Value, LangError> {
+    let text = args[0].as_text()?;
+    let n = count_arg(args.get(1), 1)?;
+    let skip = text.chars().count().saturating_sub(n);
+    Ok(Value::Text(text.chars().skip(skip).collect()))
+}
+
+pub fn mid(args: &[Value]) -> Result<Value, LangError> {
+    let text = args[0].as_text()?;
+    let start = count_arg(args.get(1), 1)?;
+    let n = count_arg(args.get(2), 0)?;
+    if start < 1 {
+        return Err(LangError::value("The start must be at least 1"));
+    }
+    Ok(Value::Text(text.chars().skip(start - 1).take(n).collect()))
+}
+
+/// Returns the 1-based position of the first argument within the second.
+pub fn find(args: &[Value]) -> Result<Value, LangError> {
+    let needle: Vec<char> = args[0].as_text()?.chars().collect();
+    let haystack: Vec<char> = args[1].as_text()?.chars().collect();
+    let start = count_arg(args.get(2), 1)?;
+    if start < 1 || start > haystack.len() + 1 {
+        return Err(LangError::value("The start is outside the text"));
+    }
+
+    (start - 1..=haystack.len())
+        .find(|&i| haystack[i..].starts_with(&needle))
+        .map(|i| Value::Number((i + 1) as f64))
+        .ok_or_else(|| LangError::value("The text was not found"))
+}
+
+/// Replaces `old` with `new`, either everywhere or only the given occurrence.
+pub fn substitute(args: &[Value]) -> Result<Value, LangError> {
+    let text = args[0].as_text()?;
+    let old = args[1].as_text()?;
+    let new = args[2].as_text()?;
+    if old.is_empty() {
+        return Ok(Value::Text(text));
+    }
+
+    let out = match args.get(3) {
+        None => text.replace(&old, &new),
+        Some(instance) => {
+            let instance = count_arg(Some(instance), 1)?;
+            if instance < 1 {
+                return Err(LangError::value("The instance must be at least 1"));
+            }
+            match text.match_indices(&old).nth(instance - 1) {
+                Some((idx, _)) => format!("{}{}{}", &text[..idx], new, &text[idx + old.len()..]),
+                None => text,
+            }
+        }
+    };
+    Ok(Value::Text(out))
+}
+
+/// Formats a number with a pattern such as `0.00`, `#,##0` or `0%`.
+pub fn text(args: &[Value]) -> Result<Value, LangError> {
+    let pattern = args[1].as_text()?;
+    match &args[0] {
+        Value::Text(s) if s.trim().parse::<f64>().is_err() => Ok(Value::Text(s.clone())),
+        val => Ok(Value::Text(format_pattern(val.as_number()?, &pattern))),
+    }
+}
+
+pub fn value(args: &[Value]) -> Result<Value, LangError> {
+    Ok(Value::Number(args[0].as_number()?))
+}
+
+/// Reads a non-negative character count, defaulting when it is omitted.
+fn count_arg(arg: Option<&Value>, default: usize) -> Result<usize, LangError> {
+    match arg {
+        None => Ok(default),
+        Some(val) => {
+            let n = val.as_number()?.trunc();
+            if n < 0.0 {
+                Err(LangError::value("The count cannot be negative"))
+            } else {
+                Ok(n as usize)
+            }
+        }
+    }
+}
+
+fn format_pattern(n: f64, pattern: &str) -> String {
+    let is_placeholder = |c: char| c == '0' || c == '#';
+    let (start, end) = match (pattern.find(is_placeholder), pattern.rfind(is_placeholder)) {
+        (Some(start), Some(end)) => (start, end + 1),
+        _ => return pattern.to_string(),
+    };
+    let (prefix, body, suffix) = (&pattern[..start], &pattern[start..end], &pattern[end..]);
+
+    let n = if suffix.contains('%') { n * 100.0 } else { n };
+    let (min_decimals, max_decimals) = match body.find('.') {
+        Some(idx) => {
+            let decimals = &body[idx + 1..];
+            (
+                decimals.chars().filter(|c| *c == '0').count(),
+                decimals.chars().filter(|c| is_placeholder(*c)).count(),
+            )
+        }
+        None => (0, 0),
+    };
+
+    let mut digits = format!("{:.*}", max_decimals, n.abs());
+    let mut decimals = max_decimals;
+    while decimals > min_decimals && digits.ends_with('0') {
+        digits.pop();
+        decimals -= 1;
+    }
+    if digits.ends_with('.') {
+        digits.pop();
+    }
+
+    if body.contains(',') {
+        let int_len = digits.find('.').unwrap_or(digits.len());
+        let (int, frac) = digits.split_at(int_len);
+        let mut grouped = String::new();
+        for (i, c) in int.chars().enumerate() {
+            if i > 0 && (int_len - i) % 3 == 0 {
+                grouped.push(',');
+            }
+            grouped.push(c);
+        }
+        digits = grouped + frac;
+    }
+
+    let sign = if n < 0.0 && digits.chars().any(|c| c.is_ascii_digit() && c != '0') {
+        "-"
+    } else {
+        ""
+    };
+    format!("{}{}{}{}", sign, prefix, digits, suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::ErrorKind;
+
+    fn text_val(s: &str) -> Value {
+        Value::Text(s.to_string())
+    }
+
+    #[test]
+    fn test_case_and_length() {
+        assert_eq!(len(&[text_val("héllo")]), Ok(Value::Number(5.0)));
+        assert_eq!(upper(&[text_val("abc")]), Ok(text_val("ABC")));
+        assert_eq!(lower(&[text_val("ABC")]), Ok(text_val("abc")));
+        assert_eq!(trim(&[text_val("  a   b ")]), Ok(text_val("a b")));
+    }
+
+    #[test]
+    fn test_concat() {
+        let range = Value::Array(vec![vec![text_val("a"), Value::Number(1.0)]]);
+        assert_eq!(
+            concat(&[range, Value::Bool(true), Value::Empty]),
+            Ok(text_val("a1TRUE"))
+        );
+    }
+
+    #[test]
+    fn test_substrings() {
+        let s = text_val("spreadsheet");
+        assert_eq!(left(std::slice::from_ref(&s)), Ok(text_val("s")));
+        assert_eq!(
+            left(&[s.clone(), Value::Number(6.0)]),
+            Ok(text_val("spread"))
+        );
+        assert_eq!(
+            right(&[s.clone(), Value::Number(5.0)]),
+            Ok(text_val("sheet"))
+        );
+        assert_eq!(right(&[s.clone(), Value::Number(50.0)]), Ok(s.clone()));
+        assert_eq!(
+            mid(&[s.clone(), Value::Number(3.0), Value::Number(4.0)]),
+            Ok(text_val("read"))
+        );
+        assert_eq!(
+            mid(&[s.clone(), Value::Number(0.0), Value::Number(4.0)]),
+            Err(ErrorKind::Value.into())
+        );
+        assert_eq!(
+            left(&[s, Value::Number(-1.0)]),
+            Err(ErrorKind::Value.into())
+        );
+    }
+
+    #[test]
+    fn test_find_and_substitute() {
+        let s = text_val("a-b-c");
+        assert_eq!(find(&[text_val("-"), s.clone()]), Ok(Value::Number(2.0)));
+        assert_eq!(
+            find(&[text_val("-"), s.clone(), Value::Number(3.0)]),
+            Ok(Value::Number(4.0))
+        );
+        assert_eq!(
+            find(&[text_val("x"), s.clone()]),
+            Err(ErrorKind::Value.into())
+        );
+        assert_eq!(
+            substitute(&[s.clone(), text_val("-"), text_val("+")]),
+            Ok(text_val("a+b+c"))
+        );
+        assert_eq!(
+            substitute(&[s, text_val("-"), text_val("+"), Value::Number(2.0)]),
+            Ok(text_val("a-b+c"))
+        );
+    }
+
+    #[test]
+    fn test_text_and_value() {
+        let cases = [
+            (3.456, "0.00", "3.46"),
+            (1234567.891, "#,##0.00", "1,234,567.89"),
+            (0.256, "0.0%", "25.6%"),
+            (-42.0, "$0", "-$42"),
+            (2.5, "0.##", "2.5"),
+            (7.0, "0", "7"),
+        ];
+        for (n, pattern, expected) in cases.iter() {
+            let out = text(&[Value::Number(*n), text_val(pattern)]);
+            assert_eq!(out, Ok(text_val(expected)), "{}", pattern);
+        }
+
+        assert_eq!(value(&[text_val(" 12.5 ")]), Ok(Value::Number(12.5)));
+        assert_eq!(value(&[text_val("abc")]), Err(ErrorKind::Value.into()));
+    }
+}
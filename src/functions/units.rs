@@ -0,0 +1,71 @@
+use super::{Arity, Coerce::*, Registry};
+use crate::parse::LangError;
+use crate::unit::Unit;
+use crate::value::Value;
+
+pub fn register(registry: &mut Registry) {
+    registry.builtin("CONVERT", Arity::range(2, 3), &[Any, Text], convert);
+}
+
+/// `CONVERT(12, "mm", "in")` converts a plain number between units, while
+/// `CONVERT(A1, "in")` shows a quantity in another unit.
+pub fn convert(args: &[Value]) -> Result<Value, LangError> {
+    if args.len() == 3 {
+        let from = Unit::named(&args[1].as_text()?)?;
+        let to = Unit::named(&args[2].as_text()?)?;
+        return Ok(Value::Number(from.convert(args[0].as_number()?, &to)?));
+    }
+
+    let to = Unit::named(&args[1].as_text()?)?;
+    match &args[0] {
+        Value::Quantity(n, unit) => Ok(Value::Quantity(unit.convert(*n, &to)?, to)),
+        Value::Error(e) => Err(e.clone()),
+        v => Err(LangError::value(format!(
+            "{} has no unit to convert from",
+            v
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::Data;
+    use crate::parse::{parse, ErrorKind};
+    use crate::position::CellPos;
+    use crate::value::Value;
+
+    #[test]
+    fn test_convert() {
+        let mut data = Data::new();
+        data.insert(CellPos::new(0, 0), "2 lb".to_string());
+
+        let (val, _) = parse("=CONVERT(1, \"in\", \"mm\")", &data).unwrap();
+        assert_eq!(val, Value::Number(25.4));
+        let (val, _) = parse("=CONVERT(A1, \"oz\")", &data).unwrap();
+        assert_eq!(val.to_string(), "32 oz");
+        let (val, _) = parse("=CONVERT(90 min, \"h\")", &data).unwrap();
+        assert_eq!(val.to_string(), "1.5 h");
+
+        assert_eq!(
+            parse("=CONVERT(A1, \"mm\")", &data),
+            Err(ErrorKind::Value.into())
+        );
+        assert_eq!(
+            parse("=CONVERT(1, \"in\", \"parsec\")", &data),
+            Err(ErrorKind::Na.into())
+        );
+        assert_eq!(
+            parse("=CONVERT(5, \"in\")", &data),
+            Err(ErrorKind::Value.into())
+        );
+
+        // Aggregates convert like `+` does, or refuse quantities
+        data.insert(CellPos::new(0, 1), "12 oz".to_string());
+        let (total, _) = parse("=A1 + A2", &data).unwrap();
+        assert_eq!(parse("=SUM(A1:A2)", &data).map(|(v, _)| v), Ok(total));
+        assert_eq!(
+            parse("=STDEV.P(A1:A2)", &data),
+            Err(ErrorKind::Value.into())
+        );
+    }
+}
@@ -1,44 +1,69 @@
-use crate::position::CellPos;
-use log::debug;
+use crate::position::{CellPos, SheetId};
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::Hash;
 
-#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
-enum Neighbor {
-    Incoming(CellPos),
-    Outgoing(CellPos),
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+enum Neighbor<N> {
+    Incoming(N),
+    Outgoing(N),
+}
+
+/// A node in the dependency graph of a workbook. Names sit between the
+/// cells they refer to and the formulas using them.
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub enum Node {
+    Cell(SheetId, CellPos),
+    Name(String),
+}
+
+/// A step of recalculating in order.
+#[derive(PartialEq, Debug)]
+pub enum Step<N> {
+    Node(N),
+    /// Nodes depending on each other, starting and ending with the same
+    /// node and following the dependents: `[a, b, a]` when `b` depends on
+    /// `a` and `a` on `b`.
+    Cycle(Vec<N>),
 }
 
 #[derive(Debug)]
-pub struct Dag {
-    adjacency_list: HashMap<CellPos, HashSet<Neighbor>>,
+pub struct Dag<N> {
+    adjacency_list: HashMap<N, HashSet<Neighbor<N>>>,
 }
 
-impl Dag {
-    pub fn new() -> Self {
+impl<N: Clone + Eq + Hash> Default for Dag<N> {
+    fn default() -> Self {
         Self {
             adjacency_list: HashMap::new(),
         }
     }
+}
+
+impl<N: Clone + Eq + Hash> Dag<N> {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-    pub fn insert(&mut self, pos: CellPos, neighbors: &[CellPos]) {
-        self.remove(pos);
+    /// Replaces the incoming edges of `pos`, keeping the nodes that depend on it.
+    pub fn insert(&mut self, pos: N, neighbors: &[N]) {
+        self.remove_incoming(&pos);
 
         for neighbor in neighbors {
-            if let Some(mut l) = self.adjacency_list.get_mut(neighbor) {
-                l.insert(Neighbor::Outgoing(pos));
+            if let Some(l) = self.adjacency_list.get_mut(neighbor) {
+                l.insert(Neighbor::Outgoing(pos.clone()));
             } else {
                 let mut hs = HashSet::new();
-                hs.insert(Neighbor::Outgoing(pos));
-                self.adjacency_list.insert(*neighbor, hs);
+                hs.insert(Neighbor::Outgoing(pos.clone()));
+                self.adjacency_list.insert(neighbor.clone(), hs);
             }
         }
 
-        let hs: HashSet<_> = neighbors.iter().map(|n| Neighbor::Incoming(*n)).collect();
-        self.adjacency_list.insert(pos, hs);
+        let l = self.adjacency_list.entry(pos).or_default();
+        l.extend(neighbors.iter().map(|n| Neighbor::Incoming(n.clone())));
     }
 
-    pub fn remove(&mut self, pos: CellPos) {
-        if self.adjacency_list.get(&pos).is_none() {
+    pub fn remove(&mut self, pos: N) {
+        if !self.adjacency_list.contains_key(&pos) {
             return;
         }
 
@@ -51,13 +76,13 @@ impl Dag {
                     self.adjacency_list
                         .get_mut(&n)
                         .unwrap()
-                        .remove(&Neighbor::Outgoing(pos));
+                        .remove(&Neighbor::Outgoing(pos.clone()));
                 }
                 Neighbor::Outgoing(n) => {
                     self.adjacency_list
                         .get_mut(&n)
                         .unwrap()
-                        .remove(&Neighbor::Incoming(pos));
+                        .remove(&Neighbor::Incoming(pos.clone()));
                 }
             }
         }
@@ -65,11 +90,35 @@ impl Dag {
         self.adjacency_list.remove(&pos);
     }
 
-    pub fn get_dependents(&self, pos: CellPos) -> Vec<CellPos> {
+    fn remove_incoming(&mut self, pos: &N) {
+        let incoming: Vec<_> = match self.adjacency_list.get_mut(pos) {
+            Some(l) => {
+                let incoming = l
+                    .iter()
+                    .filter(|x| matches!(x, Neighbor::Incoming(_)))
+                    .cloned()
+                    .collect();
+                l.retain(|x| matches!(x, Neighbor::Outgoing(_)));
+                incoming
+            }
+            None => return,
+        };
+
+        for neighbor in incoming {
+            if let Neighbor::Incoming(n) = neighbor {
+                self.adjacency_list
+                    .get_mut(&n)
+                    .unwrap()
+                    .remove(&Neighbor::Outgoing(pos.clone()));
+            }
+        }
+    }
+
+    pub fn get_dependents(&self, pos: N) -> Vec<N> {
         if let Some(l) = self.adjacency_list.get(&pos) {
             l.iter()
                 .filter_map(|x| match x {
-                    Neighbor::Outgoing(v) => Some(*v),
+                    Neighbor::Outgoing(v) => Some(v.clone()),
                     _ => None,
                 })
                 .collect()
@@ -78,35 +127,120 @@ impl Dag {
         }
     }
 
-    pub fn get_topological_sort(&self, pos: CellPos) -> Result<Vec<CellPos>, ()> {
-        let mut temporary = HashSet::new();
-        let mut permanent = HashSet::new();
-        let mut order = Vec::new();
-        let mut stack = VecDeque::new();
-        stack.push_back((pos, 0));
-
-        while stack.len() > 0 {
-            let (n, pass) = stack.pop_back().unwrap();
-            if pass == 0 {
-                if permanent.get(&n).is_some() {
-                    continue;
-                }
-                if temporary.get(&n).is_some() {
-                    return Err(());
+    fn get_dependencies(&self, pos: &N) -> Vec<N> {
+        match self.adjacency_list.get(pos) {
+            Some(l) => l
+                .iter()
+                .filter_map(|x| match x {
+                    Neighbor::Incoming(v) => Some(v.clone()),
+                    _ => None,
+                })
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Orders `pos` and everything depending on it so that each node comes
+    /// after the nodes it depends on. Nodes depending on each other in a
+    /// cycle cannot be ordered, so the cycle takes their place and the
+    /// nodes depending on it come after it.
+    pub fn get_topological_sort(&self, pos: N) -> Vec<Step<N>> {
+        // Everything depending on `pos`, in the order it was found
+        let mut reachable = vec![pos.clone()];
+        let mut found: HashSet<_> = reachable.iter().cloned().collect();
+        let mut i = 0;
+        while i < reachable.len() {
+            for m in self.get_dependents(reachable[i].clone()) {
+                if found.insert(m.clone()) {
+                    reachable.push(m);
                 }
-                temporary.insert(pos);
-                stack.push_back((n, 1));
-                for m in self.get_dependents(n) {
-                    stack.push_back((m, 0));
+            }
+            i += 1;
+        }
+
+        // How many of its dependencies each node is still waiting for
+        let mut waiting: HashMap<_, _> = reachable
+            .iter()
+            .map(|n| {
+                let deps = self.get_dependencies(n);
+                (
+                    n.clone(),
+                    deps.iter().filter(|d| found.contains(*d)).count(),
+                )
+            })
+            .collect();
+        let mut ready: VecDeque<_> = reachable
+            .iter()
+            .filter(|n| waiting[*n] == 0)
+            .cloned()
+            .collect();
+        let mut done = HashSet::new();
+        let mut steps = Vec::new();
+
+        loop {
+            while let Some(n) = ready.pop_front() {
+                done.insert(n.clone());
+                self.release(&n, &done, &mut waiting, &mut ready);
+                steps.push(Step::Node(n));
+            }
+
+            // Whatever is left waits on a cycle
+            let stuck = match reachable.iter().find(|n| !done.contains(*n)) {
+                Some(n) => n.clone(),
+                None => break,
+            };
+            let cycle = self.find_cycle(stuck, &found, &done);
+            done.extend(cycle.iter().cloned());
+            for n in &cycle[1..] {
+                self.release(n, &done, &mut waiting, &mut ready);
+            }
+            steps.push(Step::Cycle(cycle));
+        }
+        steps
+    }
+
+    /// Tells the dependents of `n`, which has been ordered, that they no
+    /// longer wait for it.
+    fn release(
+        &self,
+        n: &N,
+        done: &HashSet<N>,
+        waiting: &mut HashMap<N, usize>,
+        ready: &mut VecDeque<N>,
+    ) {
+        for m in self.get_dependents(n.clone()) {
+            if done.contains(&m) {
+                continue;
+            }
+            if let Some(count) = waiting.get_mut(&m) {
+                *count -= 1;
+                if *count == 0 {
+                    ready.push_back(m);
                 }
-            } else {
-                permanent.insert(n);
-                order.push(n);
             }
         }
+    }
 
-        order.reverse();
-        Ok(order)
+    /// Walks back from `start` through dependencies that are not `done`,
+    /// which must lead around a cycle, and returns the cycle.
+    fn find_cycle(&self, start: N, found: &HashSet<N>, done: &HashSet<N>) -> Vec<N> {
+        let mut walk = vec![start.clone()];
+        let mut index: HashMap<_, _> = vec![(start, 0)].into_iter().collect();
+        loop {
+            let next = self
+                .get_dependencies(walk.last().unwrap())
+                .into_iter()
+                .find(|d| found.contains(d) && !done.contains(d))
+                .unwrap();
+            if let Some(i) = index.get(&next) {
+                let mut cycle = walk.split_off(*i);
+                cycle.push(next);
+                cycle.reverse();
+                return cycle;
+            }
+            index.insert(next.clone(), walk.len());
+            walk.push(next);
+        }
     }
 }
 
@@ -138,7 +272,54 @@ mod tests {
 
         g.insert(p2, &[p1]);
         g.insert(p3, &[p1, p2]);
-        let dep = g.get_topological_sort(p1).unwrap();
-        assert_eq!(dep, vec![p1, p2, p3]);
+        let dep = g.get_topological_sort(p1);
+        assert_eq!(dep, vec![Step::Node(p1), Step::Node(p2), Step::Node(p3)]);
+    }
+
+    #[test]
+    fn test_graph_cycle() {
+        let mut g = Dag::new();
+        let p1 = CellPos::new(0, 0);
+        let p2 = CellPos::new(1, 0);
+        let p3 = CellPos::new(2, 0);
+        let p4 = CellPos::new(3, 0);
+
+        g.insert(p2, &[p1]);
+        g.insert(p3, &[p2]);
+        g.insert(p4, &[p3]);
+        g.insert(p2, &[p1, p3]);
+        assert_eq!(
+            g.get_topological_sort(p1),
+            vec![
+                Step::Node(p1),
+                Step::Cycle(vec![p2, p3, p2]),
+                Step::Node(p4)
+            ]
+        );
+        assert_eq!(
+            g.get_topological_sort(p3),
+            vec![Step::Cycle(vec![p3, p2, p3]), Step::Node(p4)]
+        );
+
+        g.insert(p2, &[p1]);
+        let order: Vec<_> = [p1, p2, p3, p4].iter().map(|p| Step::Node(*p)).collect();
+        assert_eq!(g.get_topological_sort(p1), order);
+
+        g.insert(p1, &[p1]);
+        assert_eq!(g.get_topological_sort(p1)[0], Step::Cycle(vec![p1, p1]));
+    }
+
+    #[test]
+    fn test_graph_reinsert_keeps_dependents() {
+        let mut g = Dag::new();
+        let p1 = CellPos::new(0, 0);
+        let p2 = CellPos::new(1, 0);
+        let p3 = CellPos::new(2, 0);
+
+        g.insert(p2, &[p1]);
+        g.insert(p3, &[p2]);
+        g.insert(p2, &[]);
+        assert_eq!(g.get_dependents(p1), vec![]);
+        assert_eq!(g.get_dependents(p2), vec![p3]);
     }
 }
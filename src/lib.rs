@@ -0,0 +1,14 @@
+pub mod ast;
+pub mod compositor;
+pub mod data;
+pub mod decimal;
+pub mod formula;
+pub mod functions;
+pub mod graph;
+pub mod logger;
+pub mod parse;
+pub mod position;
+pub mod state;
+pub mod unit;
+pub mod value;
+pub mod viewer;
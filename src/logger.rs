@@ -1,7 +1,4 @@
-use chrono;
-use fern;
 use log::debug;
-use std;
 
 pub fn init() -> Result<(), fern::InitError> {
     fern::Dispatch::new()
@@ -7,21 +7,11 @@ use termion::input::TermRead;
 use termion::raw::IntoRawMode;
 use termion::screen::AlternateScreen;
 use tui::backend::TermionBackend;
-use tui::layout::{Constraint, Direction, Layout};
-use tui::style::{Color, Modifier, Style};
+use tui::style::{Color, Style};
 use tui::widgets::{Block, Borders, Paragraph, Text, Widget};
 use tui::Terminal;
 
-use log::debug;
-
-mod compositor;
-mod data;
-mod graph;
-mod logger;
-mod parse;
-mod position;
-mod state;
-mod viewer;
+use termsheets::{compositor, data, logger, state, viewer};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Terminal initialization
@@ -31,7 +21,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let backend = TermionBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
     terminal.hide_cursor()?;
-    logger::init();
+    let _ = logger::init();
 
     let events = Events::new();
 
@@ -67,12 +57,26 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
             if let Some(edit_area) = compositor.get_area_edit() {
                 let buffer = compositor.get_buffer();
-                let texts = [Text::raw(buffer)];
+                let mut texts = vec![Text::raw(buffer)];
+                if let Some(e) = compositor.get_syntax_error() {
+                    texts.push(Text::raw(format!("\n{}^\n", " ".repeat(e.column))));
+                    texts.push(Text::styled(
+                        e.message.as_str(),
+                        Style::default().fg(Color::Red),
+                    ));
+                }
                 Paragraph::new(texts.iter())
-                    .block(Block::default().title("Edit").borders(Borders::ALL))
+                    .block(
+                        Block::default()
+                            .title(compositor.get_edit_title())
+                            .borders(Borders::ALL),
+                    )
                     .wrap(true)
                     .render(&mut f, edit_area);
             }
+
+            let status = [Text::raw(compositor.get_status())];
+            Paragraph::new(status.iter()).render(&mut f, compositor.get_area_status());
         })?;
 
         state.handle_event(events.next()?);
@@ -86,20 +90,21 @@ pub struct Events {
     rx: mpsc::Receiver<Key>,
 }
 
+impl Default for Events {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Events {
     pub fn new() -> Events {
         let (tx, rx) = mpsc::channel();
         let tx = tx.clone();
         thread::spawn(move || {
             let stdin = io::stdin();
-            for evt in stdin.keys() {
-                match evt {
-                    Ok(key) => {
-                        if let Err(_) = tx.send(key) {
-                            return;
-                        }
-                    }
-                    Err(_) => {}
+            for key in stdin.keys().flatten() {
+                if tx.send(key).is_err() {
+                    return;
                 }
             }
         });
@@ -1,132 +1,855 @@
-use crate::data::Data;
-use crate::position::CellPos;
+use crate::ast::{compile, BinOp, Expr};
+use crate::data::{Data, NameTarget};
+use crate::decimal::Decimal;
+use crate::functions::Function;
+use crate::graph::Node;
+use crate::position::{
+    column_index, column_name, row_index, row_number, CellPos, CellRange, SheetId, MAX_CELLS,
+};
+use crate::unit::{Dimension, Unit};
+use crate::value::{Lambda, Value};
 use log::debug;
-use pest::iterators::{Pair, Pairs};
+use pest::error::{ErrorVariant, LineColLocation};
+use pest::iterators::Pair;
 use pest::Parser;
 use pest_derive::Parser;
+use std::cmp::Ordering;
 use std::collections::HashSet;
 use std::fmt;
+use std::rc::Rc;
 
 #[derive(Parser)]
 #[grammar = "lang.pest"]
 pub struct LangParser;
 
-#[derive(Debug)]
-pub enum LangError {
-    ParseError,
-    OpError,
-    CellError,
-    RefError,
+/// The kinds of error a formula can give, shown the way other spreadsheets
+/// show them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The formula could not be parsed.
+    Parse,
+    Div,
+    Value,
+    Ref,
+    Name,
+    Na,
+    Num,
+    Cycle,
+    Spill,
 }
 
-impl fmt::Display for LangError {
+impl ErrorKind {
+    /// A reason for errors that were not given one.
+    fn describe(self) -> &'static str {
+        use ErrorKind::*;
+        match self {
+            Parse => "The formula could not be parsed",
+            Div => "Division by zero",
+            Value => "A value has the wrong type",
+            Ref => "The reference is not valid",
+            Name => "The name is not defined",
+            Na => "No value is available",
+            Num => "The number is not valid",
+            Cycle => "The formula refers to itself",
+            Spill => "The array has no room to spill",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        use LangError::*;
+        use ErrorKind::*;
         let val = match self {
-            ParseError => "#PARSE_ERR",
-            OpError => "#OP_ERR",
-            CellError => "#CELL_ERR",
-            RefError => "#REF_ERR",
+            Parse => "#ERROR!",
+            Div => "#DIV/0!",
+            Value => "#VALUE!",
+            Ref => "#REF!",
+            Name => "#NAME?",
+            Na => "#N/A",
+            Num => "#NUM!",
+            Cycle => "#CYCLE!",
+            Spill => "#SPILL!",
         };
         write!(f, "{}", val)
     }
 }
 
+/// An error value together with the reason for it. Errors of the same kind
+/// are equal, whatever their reasons.
+#[derive(Debug, Clone)]
+pub struct LangError {
+    pub kind: ErrorKind,
+    pub reason: String,
+}
+
+impl LangError {
+    pub fn new(kind: ErrorKind, reason: impl Into<String>) -> Self {
+        Self {
+            kind,
+            reason: reason.into(),
+        }
+    }
+
+    pub fn parse(reason: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Parse, reason)
+    }
+
+    pub fn div(reason: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Div, reason)
+    }
+
+    pub fn value(reason: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Value, reason)
+    }
+
+    pub fn reference(reason: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Ref, reason)
+    }
+
+    pub fn name(reason: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Name, reason)
+    }
+
+    pub fn na(reason: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Na, reason)
+    }
+
+    pub fn num(reason: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Num, reason)
+    }
+
+    pub fn cycle(reason: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Cycle, reason)
+    }
+
+    pub fn spill(reason: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Spill, reason)
+    }
+}
+
+impl From<ErrorKind> for LangError {
+    fn from(kind: ErrorKind) -> Self {
+        Self::new(kind, kind.describe())
+    }
+}
+
+impl PartialEq for LangError {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+impl fmt::Display for LangError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
 #[derive(Debug, PartialEq)]
-enum Operation {
+pub(crate) enum Operation {
     Div,
     Mul,
     Sub,
     Add,
+    Pow,
 }
 
-/// Note: This returns all dependent cells from the parsing step
-/// ideally this was done in a different way
-pub fn parse(source: &str, data: &Data) -> Result<(i32, Vec<CellPos>), LangError> {
-    debug!("Source string: {}", source);
-    let mut ident = LangParser::parse(Rule::ident, source).map_err(|_| LangError::ParseError)?;
-    let (val, mut deps) = handle_expression(ident.next().unwrap().into_inner(), data)?;
-    let deps = deps.drain().collect();
-    return Ok((val, deps));
+/// How deeply lambdas and name formulas may call each other before
+/// evaluation gives up with `#NUM!`.
+const MAX_DEPTH: usize = 64;
+
+/// Compiles and evaluates a formula on the active sheet, returning its
+/// value and the cells and names it depends on.
+pub fn parse(source: &str, data: &Data) -> Result<(Value, Vec<Node>), LangError> {
+    parse_in(source, data, data.active_sheet())
 }
 
-pub fn handle_expression(
-    mut pairs: Pairs<Rule>,
+/// Compiles and evaluates a formula entered on `sheet`, which is where
+/// references without a sheet name point. There is no cell for relative
+/// R1C1 references to be taken from, so they give `#REF!`.
+pub fn parse_in(
+    source: &str,
     data: &Data,
-) -> Result<(i32, HashSet<CellPos>), LangError> {
-    let op = pairs.next().unwrap();
-    debug!("{}", &op);
-    let op = match op.as_str() {
-        "add" => Ok(Operation::Add),
-        "sub" => Ok(Operation::Sub),
-        "div" => Ok(Operation::Div),
-        "mul" => Ok(Operation::Mul),
-        n => {
-            debug!("Invalid Op: {}", n);
-            Err(LangError::OpError)
-        }
-    }?;
-
-    let mut vals = Vec::new();
-    let mut dependents = HashSet::new();
-    for (idx, term) in pairs.enumerate() {
-        let val = match term.as_rule() {
-            Rule::cell => {
-                let coord = convert_pair_to_cell_coord(term);
-                dependents.insert(coord);
-                let val = data.get(coord).ok_or(LangError::RefError)?;
-                val.parse::<i32>().map_err(|_| LangError::RefError)?
+    sheet: SheetId,
+) -> Result<(Value, Vec<Node>), LangError> {
+    debug!("Source string: {}", source);
+    let (val, deps) = evaluate(&compile(source, None)?, data, sheet);
+    Ok((val?, deps))
+}
+
+/// Evaluates a compiled formula on `sheet`, returning its value and the
+/// cells and names it depends on. The dependencies are complete even when
+/// evaluation fails, so the formula is tried again once they change.
+pub fn evaluate(expr: &Expr, data: &Data, sheet: SheetId) -> (Result<Value, LangError>, Vec<Node>) {
+    let mut ctx = Context {
+        data,
+        sheet,
+        deps: HashSet::new(),
+        scope: Vec::new(),
+        depth: 0,
+    };
+    let val = match handle_term(expr, &mut ctx) {
+        Ok(Value::Lambda(_)) => Err(LangError::value("A LAMBDA must be called to give a value")),
+        out => out,
+    };
+    if val.is_err() {
+        // Evaluation stopped at the first error, before reaching the rest
+        collect_refs(expr, &mut ctx);
+    }
+    let deps = ctx.deps.drain().collect();
+    (val, deps)
+}
+
+/// The state of evaluating one formula.
+struct Context<'a> {
+    data: &'a Data,
+    /// The sheet that references without a sheet name point to.
+    sheet: SheetId,
+    deps: HashSet<Node>,
+    /// Variables bound by LET and lambda parameters, innermost last.
+    scope: Vec<(String, Value)>,
+    depth: usize,
+}
+
+impl<'a> Context<'a> {
+    fn lookup(&self, name: &str) -> Option<&Value> {
+        self.scope
+            .iter()
+            .rev()
+            .find(|(n, _)| n == name)
+            .map(|(_, val)| val)
+    }
+
+    /// Evaluates the body of a lambda or name with only the variables in
+    /// `scope` visible, failing once they have recursed too deeply.
+    fn eval_nested(
+        &mut self,
+        expr: &Expr,
+        mut scope: Vec<(String, Value)>,
+    ) -> Result<Value, LangError> {
+        if self.depth >= MAX_DEPTH {
+            debug!("Maximum depth reached evaluating {:?}", expr);
+            return Err(LangError::num("Formulas call each other too deeply"));
+        }
+
+        std::mem::swap(&mut self.scope, &mut scope);
+        self.depth += 1;
+        let out = handle_term(expr, self);
+        self.depth -= 1;
+        std::mem::swap(&mut self.scope, &mut scope);
+        out
+    }
+
+    /// Runs `f` with references without a sheet name pointing at the sheet
+    /// called `name`.
+    fn on_sheet<T>(&mut self, name: &str, f: impl FnOnce(&mut Self) -> T) -> Result<T, LangError> {
+        let sheet = self
+            .data
+            .sheet_id(name)
+            .ok_or_else(|| LangError::reference(format!("Unknown sheet {}", name)))?;
+        let outer = std::mem::replace(&mut self.sheet, sheet);
+        let out = f(self);
+        self.sheet = outer;
+        Ok(out)
+    }
+}
+
+fn handle_term(expr: &Expr, ctx: &mut Context) -> Result<Value, LangError> {
+    match expr {
+        Expr::Cell(coord) => {
+            ctx.deps.insert(Node::Cell(ctx.sheet, *coord));
+            Ok(ctx
+                .data
+                .get_in(ctx.sheet, *coord)
+                .cloned()
+                .unwrap_or_default())
+        }
+        Expr::Range(range) => {
+            let sheet = ctx.sheet;
+            ctx.deps
+                .extend(range.iter().map(|pos| Node::Cell(sheet, pos)));
+            Ok(read_range(sheet, *range, ctx.data))
+        }
+        Expr::Spill(anchor) => {
+            ctx.deps.insert(Node::Cell(ctx.sheet, *anchor));
+            let sheet = ctx.data.sheet(ctx.sheet);
+            sheet.get_spill(*anchor).cloned().ok_or_else(|| {
+                let cell = convert_cell_coord_to_str(*anchor);
+                LangError::reference(format!("{} does not hold an array", cell))
+            })
+        }
+        Expr::Sheet(name, inner) => ctx.on_sheet(name, |ctx| handle_term(inner, ctx))?,
+        Expr::Name(name) => {
+            if let Some(val) = ctx.lookup(name) {
+                return Ok(val.clone());
             }
-            Rule::int => term
-                .as_str()
-                .parse::<i32>()
-                .map_err(|_| LangError::CellError)?,
-            Rule::expr => {
-                let (val, deps) = handle_expression(term.into_inner(), data)?;
-                dependents.extend(deps);
-                val
+
+            let data = ctx.data;
+            ctx.deps.insert(Node::Name(name.clone()));
+            match data.get_name(name) {
+                Some(NameTarget::Cell(sheet, coord)) => {
+                    Ok(data.get_in(*sheet, *coord).cloned().unwrap_or_default())
+                }
+                Some(NameTarget::Range(sheet, range)) => Ok(read_range(*sheet, *range, data)),
+                Some(NameTarget::Formula(_, expr)) => ctx.eval_nested(expr, Vec::new()),
+                None => Err(LangError::name(format!("Unknown name {}", name))),
             }
-            n => {
-                debug!("Unexpected Rule: {:?}", n);
-                return Err(LangError::ParseError);
+        }
+        Expr::Call(name, args) => handle_call(name, args, ctx),
+        Expr::Number(n) => Ok(Value::Number(*n)),
+        Expr::Quantity(n, unit) => Ok(Value::Quantity(*n, unit.clone())),
+        Expr::Text(text) => Ok(Value::Text(text.clone())),
+        Expr::Bool(b) => Ok(Value::Bool(*b)),
+        Expr::Omitted => Ok(Value::Empty),
+        Expr::RefError => Err(LangError::reference("The referenced cell no longer exists")),
+        Expr::Neg(inner) => apply_unary(&handle_term(inner, ctx)?, |n| -n, Decimal::checked_neg),
+        Expr::Percent(inner) => apply_unary(
+            &handle_term(inner, ctx)?,
+            |n| n / 100.0,
+            |d| d.checked_div(Decimal::from(100)),
+        ),
+        Expr::Binary(op, lhs, rhs) => {
+            let lhs = handle_term(lhs, ctx)?;
+            let rhs = handle_term(rhs, ctx)?;
+            apply_infix(lhs, *op, rhs)
+        }
+    }
+}
+
+/// Applies a binary operator, element by element when either side is an
+/// array.
+pub fn apply_infix(lhs: Value, op: BinOp, rhs: Value) -> Result<Value, LangError> {
+    if let (Value::Array(_), _) | (_, Value::Array(_)) = (&lhs, &rhs) {
+        return Ok(lhs.broadcast(&rhs, |a, b| {
+            apply_infix(a.clone(), op, b.clone()).unwrap_or_else(Value::Error)
+        }));
+    }
+
+    let op = match op {
+        BinOp::Add => Operation::Add,
+        BinOp::Sub => Operation::Sub,
+        BinOp::Mul => Operation::Mul,
+        BinOp::Div => Operation::Div,
+        BinOp::Pow => Operation::Pow,
+        BinOp::Concat => return Ok(Value::Text(lhs.as_text()? + &rhs.as_text()?)),
+        _ => {
+            let ord = lhs.compare(&rhs)?;
+            let out = match op {
+                BinOp::Eq => ord == Ordering::Equal,
+                BinOp::Ne => ord != Ordering::Equal,
+                BinOp::Lt => ord == Ordering::Less,
+                BinOp::Le => ord != Ordering::Greater,
+                BinOp::Gt => ord == Ordering::Greater,
+                _ => ord != Ordering::Less,
+            };
+            return Ok(Value::Bool(out));
+        }
+    };
+
+    if let (Value::Quantity(_, _), _) | (_, Value::Quantity(_, _)) = (&lhs, &rhs) {
+        return quantity_result(&lhs, &op, &rhs);
+    }
+    if let Some(out) = decimal_result(&lhs, &op, &rhs) {
+        return out;
+    }
+    let out = apply_binary(&op, lhs.as_number()?, rhs.as_number()?)?;
+    date_result(&lhs, &op, &rhs, out)
+}
+
+/// Arithmetic on money stays exact when the other side is a plain number
+/// or money too. Powers and dates fall back to floating point.
+fn decimal_result(lhs: &Value, op: &Operation, rhs: &Value) -> Option<Result<Value, LangError>> {
+    let plain = |v: &Value| matches!(v, Value::Decimal(_) | Value::Number(_) | Value::Empty);
+    let money = |v: &Value| matches!(v, Value::Decimal(_));
+    if !(money(lhs) || money(rhs)) || !plain(lhs) || !plain(rhs) || *op == Operation::Pow {
+        return None;
+    }
+
+    let exact = |a: Decimal, b: Decimal| match op {
+        Operation::Add => a.checked_add(b),
+        Operation::Sub => a.checked_sub(b),
+        Operation::Mul => a.checked_mul(b),
+        _ => a.checked_div(b),
+    };
+    let out = lhs
+        .as_decimal()
+        .and_then(|a| exact(a, rhs.as_decimal()?))
+        .map(Value::Decimal);
+    Some(out)
+}
+
+/// Adding or subtracting quantities converts the right side into the unit
+/// of the left, which must measure the same thing. Scaling by a number
+/// keeps the unit, while multiplying or dividing quantities gives SI units.
+fn quantity_result(lhs: &Value, op: &Operation, rhs: &Value) -> Result<Value, LangError> {
+    let split = |val: &Value| -> Result<(f64, Option<Unit>), LangError> {
+        match val {
+            Value::Quantity(n, unit) => Ok((*n, Some(unit.clone()))),
+            v => Ok((v.as_number()?, None)),
+        }
+    };
+    let (a, lhs_unit) = split(lhs)?;
+    let (b, rhs_unit) = split(rhs)?;
+    let dimension = |unit: &Option<Unit>| unit.as_ref().map_or(Dimension::NONE, |u| u.dimension);
+
+    match (op, lhs_unit.clone(), rhs_unit.clone()) {
+        (Operation::Add, Some(unit), Some(other)) | (Operation::Sub, Some(unit), Some(other)) => {
+            let b = other.convert(b, &unit)?;
+            return Ok(Value::Quantity(apply_binary(op, a, b)?, unit));
+        }
+        // A blank cell counts as nothing of the other side's unit
+        (Operation::Add, Some(unit), None) | (Operation::Sub, Some(unit), None)
+            if rhs.is_empty() =>
+        {
+            return Ok(Value::Quantity(a, unit));
+        }
+        (Operation::Add, None, Some(unit)) | (Operation::Sub, None, Some(unit))
+            if lhs.is_empty() =>
+        {
+            return Ok(Value::Quantity(apply_binary(op, 0.0, b)?, unit));
+        }
+        (Operation::Add, _, _) | (Operation::Sub, _, _) => {
+            return Err(Unit::mismatch(dimension(&lhs_unit), dimension(&rhs_unit)));
+        }
+        (Operation::Mul, Some(unit), None) | (Operation::Div, Some(unit), None) => {
+            return Ok(Value::Quantity(apply_binary(op, a, b)?, unit));
+        }
+        (Operation::Mul, None, Some(unit)) => {
+            return Ok(Value::Quantity(apply_binary(op, a, b)?, unit));
+        }
+        _ => {}
+    }
+
+    let factor = |unit: &Option<Unit>| unit.as_ref().map_or(1.0, |u| u.factor);
+    let out = apply_binary(op, a * factor(&lhs_unit), b * factor(&rhs_unit))?;
+    let out_dimension = match op {
+        Operation::Mul => dimension(&lhs_unit).times(dimension(&rhs_unit))?,
+        Operation::Div => dimension(&lhs_unit).per(dimension(&rhs_unit))?,
+        _ => {
+            if rhs_unit.is_some() || b.fract() != 0.0 {
+                return Err(LangError::value(
+                    "A quantity can only be raised to a whole number",
+                ));
             }
-        };
-        vals.push(val);
+            dimension(&lhs_unit).pow(b as i32)?
+        }
+    };
+
+    if out_dimension == Dimension::NONE {
+        Ok(Value::Number(out))
+    } else {
+        Ok(Value::Quantity(out, Unit::base(out_dimension)))
+    }
+}
+
+/// Adding days to a date gives a date and adding to a time gives a time,
+/// while subtracting one date from another gives the days between them.
+fn date_result(lhs: &Value, op: &Operation, rhs: &Value, out: f64) -> Result<Value, LangError> {
+    let is_date = |v: &Value| matches!(v, Value::Date(_) | Value::DateTime(_));
+    let is_time = |v: &Value| matches!(v, Value::Time(_));
+    match op {
+        Operation::Add if is_date(lhs) != is_date(rhs) => Value::from_serial(out),
+        Operation::Sub if is_date(lhs) && !is_date(rhs) => Value::from_serial(out),
+        Operation::Add | Operation::Sub if is_time(lhs) && (0.0..1.0).contains(&out) => {
+            Ok(Value::Time(Value::from_serial(out)?.as_datetime()?.time()))
+        }
+        _ => Ok(Value::Number(out)),
+    }
+}
+
+/// Calls a function by name. LET variables and lambda parameters come
+/// first, then built-in functions and finally workbook names holding a
+/// LAMBDA.
+fn handle_call(name: &str, args: &[Expr], ctx: &mut Context) -> Result<Value, LangError> {
+    if let Some(val) = ctx.lookup(name).cloned() {
+        return call_lambda(val, args, ctx);
+    }
+
+    let data = ctx.data;
+    let function = match data.get_function(name) {
+        Some(function) => function,
+        None => {
+            ctx.deps.insert(Node::Name(name.to_string()));
+            return match data.get_name(name) {
+                Some(NameTarget::Formula(_, expr)) => {
+                    let val = ctx.eval_nested(expr, Vec::new())?;
+                    call_lambda(val, args, ctx)
+                }
+                _ => Err(LangError::name(format!("Unknown function {}", name))),
+            };
+        }
+    };
+
+    if !function.arity().contains(args.len()) {
+        debug!("Invalid number of arguments to {}: {}", name, args.len());
+        return Err(LangError::value(format!(
+            "Wrong number of arguments to {}",
+            name
+        )));
+    }
+
+    let mut args = Args {
+        function,
+        exprs: args,
+        ctx,
+        bound: 0,
+    };
+    function.evaluate(&mut args)
+}
+
+fn call_lambda(val: Value, args: &[Expr], ctx: &mut Context) -> Result<Value, LangError> {
+    let lambda = match val {
+        Value::Lambda(lambda) => lambda,
+        Value::Error(e) => return Err(e),
+        _ => return Err(LangError::value("Only a LAMBDA can be called")),
+    };
+    if args.len() != lambda.params.len() {
+        return Err(LangError::value(format!(
+            "The LAMBDA expects {} arguments",
+            lambda.params.len()
+        )));
+    }
+
+    let mut scope = lambda.captured.clone();
+    for (param, arg) in lambda.params.iter().zip(args) {
+        scope.push((param.clone(), handle_term(arg, ctx)?));
+    }
+    ctx.eval_nested(&lambda.body, scope)
+}
+
+/// The unevaluated arguments of a function call.
+pub struct Args<'a, 'c, 'e> {
+    function: &'a dyn Function,
+    exprs: &'e [Expr],
+    ctx: &'a mut Context<'c>,
+    /// Variables bound through `bind`, dropped with the arguments.
+    bound: usize,
+}
+
+impl<'a, 'c, 'e> Args<'a, 'c, 'e> {
+    pub fn len(&self) -> usize {
+        self.exprs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exprs.is_empty()
+    }
+
+    /// Evaluates the argument at `index`, coerced as the function asks.
+    pub fn eval(&mut self, index: usize) -> Result<Value, LangError> {
+        let val = handle_term(&self.exprs[index], self.ctx)?;
+        self.function.coerce(index).apply(val)
+    }
+
+    /// Records the cells the argument at `index` refers to without
+    /// evaluating it, so the caller is recalculated when they change.
+    pub fn skip(&mut self, index: usize) {
+        if let Some(expr) = self.exprs.get(index) {
+            collect_refs(expr, self.ctx);
+        }
+    }
+
+    /// Evaluates every argument in order.
+    pub fn values(&mut self) -> Result<Vec<Value>, LangError> {
+        (0..self.len()).map(|i| self.eval(i)).collect()
     }
 
-    if (op == Operation::Sub || op == Operation::Div) && vals.len() > 2 {
-        debug!("Too many values");
-        return Err(LangError::OpError);
+    /// The argument at `index` if it is a bare name such as `x`, uppercased.
+    pub fn identifier(&self, index: usize) -> Option<String> {
+        match self.exprs.get(index)? {
+            Expr::Name(name) => Some(name.clone()),
+            _ => None,
+        }
+    }
+
+    /// Makes `name` a variable for the arguments evaluated after this.
+    pub fn bind(&mut self, name: String, val: Value) {
+        self.ctx.scope.push((name, val));
+        self.bound += 1;
+    }
+
+    /// Turns the argument at `body` into a lambda taking `params`. Variables
+    /// bound so far are captured.
+    pub fn lambda(&mut self, params: Vec<String>, body: usize) -> Value {
+        let captured = self.ctx.scope.clone();
+        let mark = captured.len();
+        self.ctx
+            .scope
+            .extend(params.iter().map(|p| (p.clone(), Value::Empty)));
+        self.skip(body);
+        self.ctx.scope.truncate(mark);
+
+        Value::Lambda(Rc::new(Lambda {
+            params,
+            body: Rc::new(self.exprs[body].clone()),
+            captured,
+        }))
+    }
+}
+
+impl Drop for Args<'_, '_, '_> {
+    fn drop(&mut self) {
+        let len = self.ctx.scope.len() - self.bound;
+        self.ctx.scope.truncate(len);
+    }
+}
+
+/// Records the cells referenced by `expr` without evaluating it.
+fn collect_refs(expr: &Expr, ctx: &mut Context) {
+    match expr {
+        Expr::Cell(pos) | Expr::Spill(pos) => {
+            ctx.deps.insert(Node::Cell(ctx.sheet, *pos));
+        }
+        Expr::Range(range) => {
+            let sheet = ctx.sheet;
+            ctx.deps
+                .extend(range.iter().map(|pos| Node::Cell(sheet, pos)));
+        }
+        Expr::Sheet(name, inner) => {
+            let _ = ctx.on_sheet(name, |ctx| collect_refs(inner, ctx));
+        }
+        Expr::Name(name) => {
+            if ctx.lookup(name).is_none() {
+                ctx.deps.insert(Node::Name(name.clone()));
+            }
+        }
+        Expr::Call(name, args) => {
+            if ctx.lookup(name).is_none() && ctx.data.get_function(name).is_none() {
+                ctx.deps.insert(Node::Name(name.clone()));
+            }
+            args.iter().for_each(|arg| collect_refs(arg, ctx));
+        }
+        Expr::Neg(inner) | Expr::Percent(inner) => collect_refs(inner, ctx),
+        Expr::Binary(_, lhs, rhs) => {
+            collect_refs(lhs, ctx);
+            collect_refs(rhs, ctx);
+        }
+        Expr::Number(_)
+        | Expr::Quantity(_, _)
+        | Expr::Text(_)
+        | Expr::Bool(_)
+        | Expr::Omitted
+        | Expr::RefError => {}
     }
+}
 
+/// Applies `f` to a number, or to each number of an array. Quantities keep
+/// their unit and money is worked out exactly with `exact`.
+fn apply_unary(
+    val: &Value,
+    f: impl Fn(f64) -> f64,
+    exact: impl Fn(Decimal) -> Result<Decimal, LangError>,
+) -> Result<Value, LangError> {
+    let apply = |v: &Value| match v {
+        Value::Quantity(n, unit) => Ok(Value::Quantity(f(*n), unit.clone())),
+        Value::Decimal(d) => exact(*d).map(Value::Decimal),
+        v => Ok(Value::Number(f(v.as_number()?))),
+    };
+    match val {
+        Value::Array(_) => Ok(val.map(|v| apply(v).unwrap_or_else(Value::Error))),
+        v => apply(v),
+    }
+}
+
+fn apply_binary(op: &Operation, lhs: f64, rhs: f64) -> Result<f64, LangError> {
     let out = match op {
-        Operation::Add => vals.iter().sum(),
-        Operation::Mul => vals.iter().product(),
-        Operation::Sub => vals[0] - vals[1],
-        Operation::Div => vals[0] / vals[1],
+        Operation::Add => lhs + rhs,
+        Operation::Sub => lhs - rhs,
+        Operation::Mul => lhs * rhs,
+        Operation::Div => {
+            if rhs == 0.0 {
+                return Err(LangError::div("Division by zero"));
+            }
+            lhs / rhs
+        }
+        Operation::Pow => lhs.powf(rhs),
     };
 
-    Ok((out, dependents))
+    if out.is_finite() {
+        Ok(out)
+    } else {
+        Err(LangError::num("The result is not a finite number"))
+    }
 }
 
-pub fn convert_pair_to_cell_coord(pair: Pair<Rule>) -> CellPos {
-    let mut pair = pair.into_inner();
+/// Where and why a formula could not be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+    /// The character the problem was found at, counting from 0.
+    pub column: usize,
+    pub message: String,
+}
+
+impl From<pest::error::Error<Rule>> for SyntaxError {
+    fn from(e: pest::error::Error<Rule>) -> Self {
+        let column = match e.line_col {
+            LineColLocation::Pos((_, col)) | LineColLocation::Span((_, col), _) => col - 1,
+        };
+        let message = match e.variant {
+            ErrorVariant::ParsingError { positives, .. } => describe_expected(&positives),
+            ErrorVariant::CustomError { message } => message,
+        };
+        Self { column, message }
+    }
+}
+
+/// Describes the rules pest expected in words, such as "Expected a value
+/// or an operator".
+fn describe_expected(rules: &[Rule]) -> String {
+    let mut kinds: Vec<&str> = Vec::new();
+    for rule in rules {
+        let kind = match rule {
+            Rule::EOI => "the end of the formula",
+            Rule::op => "a function",
+            Rule::add | Rule::sub | Rule::mul | Rule::div | Rule::pow | Rule::concat => {
+                "an operator"
+            }
+            Rule::eq | Rule::ne | Rule::lt | Rule::le | Rule::gt | Rule::ge | Rule::percent => {
+                "an operator"
+            }
+            Rule::col_abs | Rule::row_abs | Rule::cell_alpha | Rule::cell_number => "a cell",
+            Rule::r1c1_row | Rule::r1c1_col | Rule::r1c1_offset | Rule::r1c1_index => "a cell",
+            Rule::unit => "a unit",
+            _ => "a value",
+        };
+        if !kinds.contains(&kind) {
+            kinds.push(kind);
+        }
+    }
+
+    match kinds.split_last() {
+        None => "Unexpected character".to_string(),
+        Some((last, [])) => format!("Expected {}", last),
+        Some((last, rest)) => format!("Expected {} or {}", rest.join(", "), last),
+    }
+}
+
+/// Checks that a formula such as `=A1 + 2` parses, without evaluating it.
+pub fn check_syntax(source: &str) -> Result<(), SyntaxError> {
+    LangParser::parse(Rule::ident, source)
+        .map(|_| ())
+        .map_err(SyntaxError::from)
+}
+
+pub(crate) fn parse_error(e: pest::error::Error<Rule>) -> LangError {
+    let e = SyntaxError::from(e);
+    LangError::parse(format!("{} at column {}", e.message, e.column + 1))
+}
+
+fn read_range(sheet: SheetId, range: CellRange, data: &Data) -> Value {
+    let cells: Vec<_> = range.iter().collect();
+    let rows = cells
+        .chunks(range.width())
+        .map(|row| {
+            row.iter()
+                .map(|coord| data.get_in(sheet, *coord).cloned().unwrap_or_default())
+                .collect()
+        })
+        .collect();
+    Value::Array(rows)
+}
+
+/// Finds the sheet named by a `sheet` pair.
+fn resolve_sheet(pair: Pair<Rule>, data: &Data) -> Result<SheetId, LangError> {
+    let name = convert_pair_to_sheet_name(pair);
+    data.sheet_id(&name)
+        .ok_or_else(|| LangError::reference(format!("Unknown sheet {}", name)))
+}
+
+/// Parses what a workbook name refers to: a reference such as `B2`,
+/// `A1:C3` or `Sheet2!A1`, or a formula such as `=LAMBDA(x, x*2)`.
+/// References without a sheet name are on the active sheet.
+pub fn parse_name_target(source: &str, data: &Data) -> Result<NameTarget, LangError> {
+    let source = source.trim();
+    if source.starts_with('=') {
+        let expr = compile(source, None)?;
+        return Ok(NameTarget::Formula(source.to_string(), Rc::new(expr)));
+    }
+
+    let mut pairs = LangParser::parse(Rule::reference, source).map_err(parse_error)?;
+    let mut pair = pairs.next().unwrap();
+    let mut sheet = data.active_sheet();
+    if pair.as_rule() == Rule::sheet_ref {
+        let mut inner = pair.into_inner();
+        sheet = resolve_sheet(inner.next().unwrap(), data)?;
+        pair = inner.next().unwrap();
+    }
+    match pair.as_rule() {
+        Rule::range => Ok(NameTarget::Range(sheet, convert_pair_to_cell_range(pair)?)),
+        Rule::cell => Ok(NameTarget::Cell(sheet, convert_pair_to_cell_coord(pair)?)),
+        _ => Err(LangError::reference("A name must refer to a cell or range")),
+    }
+}
+
+/// Checks that `name` can be used as a workbook name, that is it is an
+/// identifier that cannot be mistaken for a cell or a boolean.
+pub fn is_valid_name(name: &str) -> bool {
+    LangParser::parse(Rule::name_def, name).is_ok()
+        && LangParser::parse(Rule::reference, name).is_err()
+        && LangParser::parse(Rule::r1c1, name).map_or(true, |p| p.as_str() != name)
+        && !name.eq_ignore_ascii_case("TRUE")
+        && !name.eq_ignore_ascii_case("FALSE")
+}
+
+pub fn convert_cell_coord_to_str(pos: CellPos) -> String {
+    format!("{}{}", column_name(pos.x), row_number(pos.y))
+}
+
+/// Writes a sheet name the way a reference needs it, quoting names that
+/// are not plain identifiers: `Sheet2` or `'My Sheet'`.
+pub fn quote_sheet_name(name: &str) -> String {
+    match LangParser::parse(Rule::sheet_name, name) {
+        Ok(pairs) if pairs.as_str() == name => name.to_string(),
+        _ => format!("'{}'", name.replace('\'', "''")),
+    }
+}
+
+/// The sheet name of a `sheet` pair, without any quotes.
+pub fn convert_pair_to_sheet_name(pair: Pair<Rule>) -> String {
+    let inner = pair.into_inner().next().unwrap();
+    match inner.as_rule() {
+        Rule::quoted_sheet => inner.as_str().replace("''", "'"),
+        _ => inner.as_str().to_string(),
+    }
+}
+
+/// The cell of a `cell` pair, which is off the sheet if its column is
+/// past `XFD`.
+pub fn convert_pair_to_cell_coord(pair: Pair<Rule>) -> Result<CellPos, LangError> {
+    let source = pair.as_str();
+    let mut pair = pair
+        .into_inner()
+        .filter(|p| p.as_rule() != Rule::col_abs && p.as_rule() != Rule::row_abs);
     let alpha = pair.next().unwrap().as_str();
     let number = pair.next().unwrap().as_str();
-    let coord_0 = convert_alpha_str_to_num(alpha);
-    let coord_1: usize = number.parse().unwrap();
-    let coord_1 = coord_1;
-    return CellPos::new(coord_0, coord_1);
+    match (column_index(alpha), row_index(number)) {
+        (Some(x), Some(y)) => Ok(CellPos::new(x, y)),
+        _ => Err(LangError::reference(format!(
+            "{} is not on the sheet",
+            source
+        ))),
+    }
 }
 
-pub fn convert_alpha_str_to_num(alpha: &str) -> usize {
-    let mut acc = 0;
-    for (i, c) in alpha.chars().enumerate() {
-        let digit = c.to_digit(26).unwrap() as usize;
-        let digit = digit - 10;
-        acc += digit + (i * 26);
+pub fn convert_pair_to_cell_range(pair: Pair<Rule>) -> Result<CellRange, LangError> {
+    let mut pair = pair.into_inner();
+    let start = convert_pair_to_cell_coord(pair.next().unwrap())?;
+    let end = convert_pair_to_cell_coord(pair.next().unwrap())?;
+    checked_range(start, end)
+}
+
+/// The range between two corners, unless it has too many cells to read.
+pub fn checked_range(start: CellPos, end: CellPos) -> Result<CellRange, LangError> {
+    let range = CellRange::new(start, end);
+    if !matches!(range.cell_count(), Some(count) if count <= MAX_CELLS) {
+        return Err(LangError::reference(format!(
+            "{}:{} has more than {} cells",
+            convert_cell_coord_to_str(range.start),
+            convert_cell_coord_to_str(range.end),
+            MAX_CELLS
+        )));
     }
-    return acc;
+    Ok(range)
 }
 
 #[cfg(test)]
@@ -137,13 +860,279 @@ mod tests {
     fn test_parse() {
         let input = "=add 2 (sub (mul 2 2) 3)";
         let (val, _) = parse(input, &Data::new()).unwrap();
-        assert_eq!(val, 3);
+        assert_eq!(val, Value::Number(3.0));
+    }
+
+    #[test]
+    fn test_parse_values() {
+        let mut data = Data::new();
+        data.insert(CellPos::new(0, 0), "1.5".to_string());
+        data.insert(CellPos::new(1, 0), "text".to_string());
+
+        let (val, _) = parse("=mul A1 2.5", &data).unwrap();
+        assert_eq!(val, Value::Number(3.75));
+
+        let (val, _) = parse("=add A1 B2", &data).unwrap();
+        assert_eq!(val, Value::Number(1.5));
+
+        let out = parse("=add A1 B1", &data);
+        assert_eq!(out, Err(ErrorKind::Value.into()));
+
+        let out = parse("=div A1 0", &data);
+        assert_eq!(out, Err(ErrorKind::Div.into()));
+    }
+
+    #[test]
+    fn test_parse_infix() {
+        let mut data = Data::new();
+        data.insert(CellPos::new(0, 0), "4".to_string());
+        data.insert(CellPos::new(1, 1), "2".to_string());
+        data.insert(CellPos::new(2, 3), "10".to_string());
+
+        let (val, deps) = parse("=A1+B2*3-(C4/2)", &data).unwrap();
+        assert_eq!(val, Value::Number(5.0));
+        assert_eq!(deps.len(), 3);
+
+        let cases = [
+            ("=1+2*3", 7.0),
+            ("=(1+2)*3", 9.0),
+            ("=10-4-3", 3.0),
+            ("=2^3^2", 64.0),
+            ("=-2^2", 4.0),
+            ("=-A1+1", -3.0),
+            ("=50%*A1", 2.0),
+            ("=2*-3", -6.0),
+            ("=add (A1*2) 1", 9.0),
+        ];
+        for (input, expected) in cases.iter() {
+            let (val, _) = parse(input, &data).unwrap();
+            assert_eq!(val, Value::Number(*expected), "{}", input);
+        }
+
+        assert_eq!(parse("=1/(A1-4)", &data), Err(ErrorKind::Div.into()));
+        assert_eq!(parse("=1+", &data), Err(ErrorKind::Parse.into()));
+    }
+
+    #[test]
+    fn test_parse_ranges() {
+        let mut data = Data::new();
+        data.insert(CellPos::new(0, 0), "1".to_string());
+        data.insert(CellPos::new(0, 1), "2".to_string());
+        data.insert(CellPos::new(1, 0), "label".to_string());
+        data.insert(CellPos::new(1, 1), "4".to_string());
+
+        let (val, deps) = parse("=SUM(A1:B3)", &data).unwrap();
+        assert_eq!(val, Value::Number(7.0));
+        assert_eq!(deps.len(), 6);
+
+        let (val, _) = parse("=sum A1:A2 10", &data).unwrap();
+        assert_eq!(val, Value::Number(13.0));
+
+        let (val, _) = parse("=AVERAGE(A1:B2)*2+COUNT(A1:B2)-counta(B1:B2)", &data).unwrap();
+        assert_eq!(val, Value::Number(7.0 / 3.0 * 2.0 + 3.0 - 2.0));
+
+        let (val, _) = parse("=MAX(A2:A1, MIN(B2, 3))", &data).unwrap();
+        assert_eq!(val, Value::Number(3.0));
+
+        assert_eq!(
+            parse("=SUM(A1:XFD1048576)", &data),
+            Err(ErrorKind::Ref.into())
+        );
+        assert_eq!(
+            parse_name_target("A1:B1048576", &data),
+            Err(ErrorKind::Ref.into())
+        );
+        assert_eq!(
+            parse("=SUM(A1:B9223372036854775809)", &data),
+            Err(ErrorKind::Ref.into())
+        );
+        assert_eq!(
+            parse("=SUM(A1:A18446744073709551615)", &data),
+            Err(ErrorKind::Ref.into())
+        );
+
+        let (val, _) = parse("=A1:A2*2", &data).unwrap();
+        assert_eq!(
+            val,
+            Value::Array(vec![vec![Value::Number(2.0)], vec![Value::Number(4.0)]])
+        );
+        let (val, _) = parse("=-B1:B2>1", &data).unwrap();
+        assert_eq!(
+            val,
+            Value::Array(vec![
+                vec![Value::Error(ErrorKind::Value.into())],
+                vec![Value::Bool(false)]
+            ])
+        );
+        assert_eq!(parse("=NOPE(A1)", &data), Err(ErrorKind::Name.into()));
+    }
+
+    #[test]
+    fn test_parse_text() {
+        let mut data = Data::new();
+        data.insert(CellPos::new(0, 0), "Widget".to_string());
+        data.insert(CellPos::new(1, 0), "42".to_string());
+
+        let (val, _) = parse("=UPPER(LEFT(A1, 3)) & \"-\" & B1 + 1", &data).unwrap();
+        assert_eq!(val, Value::Text("WID-43".to_string()));
+
+        let (val, _) = parse("=\"say \"\"hi\"\"\"", &data).unwrap();
+        assert_eq!(val, Value::Text("say \"hi\"".to_string()));
+
+        let (val, _) = parse("=len \"a b\"", &data).unwrap();
+        assert_eq!(val, Value::Number(3.0));
+
+        assert_eq!(parse("=A1*2", &data), Err(ErrorKind::Value.into()));
+    }
+
+    #[test]
+    fn test_parse_logic() {
+        let mut data = Data::new();
+        data.insert(CellPos::new(0, 0), "150".to_string());
+        data.insert(CellPos::new(1, 0), "abc".to_string());
+
+        let cases = [
+            ("=A1>100", Value::Bool(true)),
+            ("=A1+1=151", Value::Bool(true)),
+            ("=B1<>\"ABC\"", Value::Bool(false)),
+            ("=1<=1", Value::Bool(true)),
+            ("=true", Value::Bool(true)),
+            ("=NOT(FALSE)", Value::Bool(true)),
+            ("=AND(A1>100, B1=\"abc\")", Value::Bool(true)),
+            ("=OR(A1<0, FALSE)", Value::Bool(false)),
+            ("=XOR(TRUE, TRUE, TRUE)", Value::Bool(true)),
+            (
+                "=IF(A1>100, \"over\", \"ok\")",
+                Value::Text("over".to_string()),
+            ),
+            ("=IF(A1<100, 1)", Value::Bool(false)),
+            ("=IFS(A1<100, 1, A1<200, 2)", Value::Number(2.0)),
+            ("=SWITCH(B1, \"x\", 1, \"abc\", 2)", Value::Number(2.0)),
+            ("=SWITCH(A1, 1, 1, 0)", Value::Number(0.0)),
+        ];
+        for (input, expected) in cases.iter() {
+            let (val, _) = parse(input, &data).unwrap();
+            assert_eq!(&val, expected, "{}", input);
+        }
+
+        assert_eq!(parse("=IFS(A1<0, 1)", &data), Err(ErrorKind::Na.into()));
+        assert_eq!(parse("=IF(B1, 1, 2)", &data), Err(ErrorKind::Value.into()));
+    }
+
+    #[test]
+    fn test_if_is_lazy() {
+        let mut data = Data::new();
+        data.insert(CellPos::new(0, 0), "0".to_string());
+
+        let (val, deps) = parse("=IF(A1=0, 0, 1/A1 + B1)", &data).unwrap();
+        assert_eq!(val, Value::Number(0.0));
+        assert!(deps.contains(&Node::Cell(data.active_sheet(), CellPos::new(1, 0))));
+
+        let (val, _) = parse("=IFS(A1=0, \"zero\", 1/A1>1, \"big\")", &data).unwrap();
+        assert_eq!(val, Value::Text("zero".to_string()));
+    }
+
+    #[test]
+    fn test_parse_names() {
+        let mut data = Data::new();
+        data.insert(CellPos::new(1, 1), "2.5".to_string());
+        data.insert(CellPos::new(2, 1), "4".to_string());
+        data.define_name("price", "B2").unwrap();
+        data.define_name("Qty", "C2:C3").unwrap();
+
+        let (val, deps) = parse("=mul price SUM(qty)", &data).unwrap();
+        assert_eq!(val, Value::Number(10.0));
+        assert_eq!(deps.len(), 2);
+        assert!(deps.contains(&Node::Name("PRICE".to_string())));
+        assert!(deps.contains(&Node::Name("QTY".to_string())));
+
+        assert_eq!(parse("=missing+1", &data), Err(ErrorKind::Name.into()));
+        assert!(!is_valid_name("A1"));
+        assert!(!is_valid_name("true"));
+        assert!(!is_valid_name("2x"));
+        assert!(is_valid_name("tax_rate"));
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let cases = [
+            ("=add 2 (sub 3", 13, "Expected a value or a unit"),
+            (
+                "=1 2",
+                3,
+                "Expected the end of the formula, an operator or a unit",
+            ),
+            ("=SUM(1 2)", 7, "Expected an operator or a unit"),
+            ("=", 1, "Expected a function or a value"),
+        ];
+        for (input, column, message) in cases.iter() {
+            let e = check_syntax(input).unwrap_err();
+            assert_eq!(
+                (e.column, e.message.as_str()),
+                (*column, *message),
+                "{}",
+                input
+            );
+        }
+        assert_eq!(check_syntax("=SUM(A1:A3) * 2"), Ok(()));
+
+        let e = parse("=1 +", &Data::new()).unwrap_err();
+        assert_eq!(e.reason, "Expected a value at column 5");
+    }
+
+    #[test]
+    fn test_columns_and_rows() {
+        let mut data = Data::new();
+        data.insert(CellPos::new(27, 0), "1".to_string());
+        data.insert(CellPos::new(52, 1), "2".to_string());
+        data.insert(CellPos::new(16383, 2), "3".to_string());
+        let (val, _) = parse("=AB1 + BA2 * 10 + XFD3 * 100", &data).unwrap();
+        assert_eq!(val, Value::Number(321.0));
+
+        assert_eq!(parse("=XFE1", &data), Err(ErrorKind::Ref.into()));
+        assert_eq!(parse("=A0", &data), Err(ErrorKind::Name.into()));
+        assert_eq!(convert_cell_coord_to_str(CellPos::new(27, 0)), "AB1");
+    }
+
+    #[test]
+    fn test_quantities() {
+        let mut data = Data::new();
+        data.insert(CellPos::new(0, 0), "5 kg".to_string());
+        data.insert(CellPos::new(0, 1), "4 s".to_string());
+
+        let cases = [
+            ("=12 mm + 1 in", "37.4 mm"),
+            ("=1in - 5.4 mm", "0.7874015748031495 in"),
+            ("=A1 * 2", "10 kg"),
+            ("=3 * 20 min", "60 min"),
+            ("=10 m / A2", "2.5 m/s"),
+            ("=2 m * 3 m", "6 m^2"),
+            ("=(3 m) ^ 2", "9 m^2"),
+            ("=1 / A2", "0.25 1/s"),
+            ("=1 km / 1 mi", "0.621371192237334"),
+            ("=A1 > 10 lb", "TRUE"),
+            ("=-A1 + B1", "-5 kg"),
+        ];
+        for (input, expected) in cases.iter() {
+            let (val, _) = parse(input, &data).unwrap();
+            assert_eq!(val.to_string(), *expected, "{}", input);
+        }
+
+        assert_eq!(parse("=A1 + 1 m", &data), Err(ErrorKind::Value.into()));
+        assert_eq!(parse("=A1 + 1", &data), Err(ErrorKind::Value.into()));
+        assert_eq!(parse("=A1 < 1 h", &data), Err(ErrorKind::Value.into()));
+        assert_eq!(parse("=A1 ^ 0.5", &data), Err(ErrorKind::Value.into()));
+        assert_eq!(
+            parse("=(1 m*1 m)^2147483647", &data),
+            Err(ErrorKind::Num.into())
+        );
     }
 
     #[test]
-    fn test_convert() {
-        let input = "AB";
-        let out = convert_alpha_str_to_num(&input);
-        assert_eq!(out, 27);
+    fn test_range_dependency() {
+        let mut data = Data::new();
+        data.insert(CellPos::new(1, 0), "=SUM(A1:A3)".to_string());
+        data.insert(CellPos::new(0, 2), "5".to_string());
+        assert_eq!(data.get(CellPos::new(1, 0)), Some(&Value::Number(5.0)));
     }
 }
@@ -1,6 +1,53 @@
 use std::ops::{Add, Sub};
 
-#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+/// The number of columns on a sheet, the last being `XFD`.
+pub const MAX_COLUMNS: usize = 16384;
+
+/// The most cells a range or an array may hold.
+pub const MAX_CELLS: usize = 1_048_576;
+
+/// The index of a column from its letters, which count in bijective base
+/// 26: `A` is 0, `Z` is 25 and `AA` is 26. Columns past `XFD` have none.
+pub fn column_index(letters: &str) -> Option<usize> {
+    if letters.is_empty() {
+        return None;
+    }
+    let mut n = 0;
+    for c in letters.chars() {
+        if !c.is_ascii_alphabetic() {
+            return None;
+        }
+        n = n * 26 + (c.to_ascii_uppercase() as u8 - b'A') as usize + 1;
+        if n > MAX_COLUMNS {
+            return None;
+        }
+    }
+    Some(n - 1)
+}
+
+/// The letters of the column at `index`, the inverse of [`column_index`].
+pub fn column_name(index: usize) -> String {
+    let mut n = index + 1;
+    let mut out = Vec::new();
+    while n > 0 {
+        n -= 1;
+        out.push((b'A' + (n % 26) as u8) as char);
+        n /= 26;
+    }
+    out.iter().rev().collect()
+}
+
+/// The index of a row from its number, which counts from 1 as in `A1`.
+pub fn row_index(number: &str) -> Option<usize> {
+    number.parse::<usize>().ok()?.checked_sub(1)
+}
+
+/// The number a row is shown and referred to by.
+pub fn row_number(index: usize) -> usize {
+    index + 1
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, Default)]
 pub struct CellPos {
     pub x: usize,
     pub y: usize,
@@ -18,12 +65,6 @@ impl CellPos {
     }
 }
 
-impl Default for CellPos {
-    fn default() -> CellPos {
-        CellPos { x: 0, y: 0 }
-    }
-}
-
 impl Add for CellPos {
     type Output = CellPos;
 
@@ -45,3 +86,99 @@ impl Sub for CellPos {
         }
     }
 }
+
+/// Identifies a sheet of a workbook. Ids are never reused, so they keep
+/// pointing at the same sheet when it is renamed.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, Default)]
+pub struct SheetId(pub usize);
+
+/// A rectangular block of cells, such as `A1:B10`.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub struct CellRange {
+    pub start: CellPos,
+    pub end: CellPos,
+}
+
+impl CellRange {
+    /// Creates the range spanned by two corners, given in any order.
+    pub fn new(a: CellPos, b: CellPos) -> Self {
+        Self {
+            start: CellPos::new(a.x.min(b.x), a.y.min(b.y)),
+            end: CellPos::new(a.x.max(b.x), a.y.max(b.y)),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.end.x - self.start.x + 1
+    }
+
+    pub fn height(&self) -> usize {
+        self.end.y - self.start.y + 1
+    }
+
+    /// The number of cells in the range, or `None` if it doesn't fit in a `usize`.
+    pub fn cell_count(&self) -> Option<usize> {
+        let width = (self.end.x - self.start.x).checked_add(1)?;
+        let height = (self.end.y - self.start.y).checked_add(1)?;
+        width.checked_mul(height)
+    }
+
+    pub fn contains(&self, pos: CellPos) -> bool {
+        (self.start.x..=self.end.x).contains(&pos.x) && (self.start.y..=self.end.y).contains(&pos.y)
+    }
+
+    /// Iterates over the cells of the range row by row.
+    pub fn iter(&self) -> impl Iterator<Item = CellPos> {
+        let CellRange { start, end } = *self;
+        (start.y..=end.y).flat_map(move |y| (start.x..=end.x).map(move |x| CellPos::new(x, y)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_columns() {
+        let cases = [
+            ("A", 0),
+            ("Z", 25),
+            ("AA", 26),
+            ("AB", 27),
+            ("AZ", 51),
+            ("BA", 52),
+            ("ZZ", 701),
+            ("AAA", 702),
+            ("XFD", 16383),
+        ];
+        for (letters, index) in cases.iter() {
+            assert_eq!(column_index(letters), Some(*index));
+            assert_eq!(column_name(*index), *letters);
+        }
+        assert_eq!(column_index("xfd"), Some(16383));
+        assert_eq!(column_index("XFE"), None);
+        assert_eq!(column_index(""), None);
+
+        assert_eq!(row_index("1"), Some(0));
+        assert_eq!(row_index("0"), None);
+        assert_eq!(row_number(0), 1);
+    }
+
+    #[test]
+    fn test_range() {
+        let range = CellRange::new(CellPos::new(1, 2), CellPos::new(0, 1));
+        assert_eq!(range.start, CellPos::new(0, 1));
+        assert_eq!(range.width(), 2);
+
+        let cells: Vec<_> = range.iter().collect();
+        assert_eq!(
+            cells,
+            vec![
+                CellPos::new(0, 1),
+                CellPos::new(1, 1),
+                CellPos::new(0, 2),
+                CellPos::new(1, 2)
+            ]
+        );
+    }
+}
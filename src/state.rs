@@ -1,60 +1,184 @@
 use crate::data::Data;
-use crate::position::CellPos;
+use crate::formula::Notation;
+use crate::position::{CellPos, CellRange};
 use termion::event::Key;
 
 pub struct StateInfo {
     pub cursor_pos: CellPos,
     pub mode: StateVal,
     pub buffer: String,
+    pub status: String,
 }
 
 #[derive(PartialEq, Copy, Clone)]
 pub enum StateVal {
     Normal,
     Insert,
+    Command,
     Exit,
 }
 
+enum DataUpdate {
+    Insert(CellPos, String),
+    Copy(CellPos, CellPos),
+    Fill(CellPos, CellRange),
+    Command(String),
+}
+
 pub struct State {
     val: StateVal,
     cursor_pos: CellPos,
     buffer: String,
-    data_updates: Vec<(CellPos, String)>,
+    status: String,
+    clipboard: Option<CellPos>,
+    data_updates: Vec<DataUpdate>,
 }
 
-impl State {
-    pub fn new() -> Self {
+impl Default for State {
+    fn default() -> Self {
         Self {
             val: StateVal::Normal,
             cursor_pos: CellPos::default(),
             buffer: String::new(),
+            status: String::new(),
+            clipboard: None,
             data_updates: Vec::new(),
         }
     }
+}
+
+impl State {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
     pub fn get_info(&self) -> StateInfo {
         StateInfo {
             cursor_pos: self.cursor_pos,
             mode: self.val,
             buffer: self.buffer.clone(),
+            status: self.status.clone(),
         }
     }
 
     pub fn update_data(&mut self, data: &mut Data) {
-        for (pos, val) in self.data_updates.drain(..) {
-            data.insert(pos, val);
+        let updates: Vec<_> = self.data_updates.drain(..).collect();
+        for update in updates {
+            match update {
+                DataUpdate::Insert(pos, val) => data.insert(pos, val),
+                DataUpdate::Copy(from, to) => data.copy(from, to),
+                DataUpdate::Fill(from, range) => data.fill(from, range),
+                DataUpdate::Command(command) => self.run_command(&command, data),
+            }
         }
     }
 
+    /// Runs a command entered after `:`.
+    ///
+    /// - `name <name> <cell, range or =formula>` defines a workbook name
+    /// - `unname <name>` deletes it
+    /// - `names` lists every name
+    /// - `sheet <name>` switches to another sheet
+    /// - `newsheet <name>` adds a sheet and switches to it
+    /// - `rename <name>` renames the current sheet
+    /// - `delsheet` deletes the current sheet
+    /// - `sheets` lists every sheet
+    /// - `notation [a1|r1c1]` switches how formulas are shown, or toggles it
+    fn run_command(&mut self, command: &str, data: &mut Data) {
+        let mut args = command.split_whitespace();
+        self.status = match (args.next(), args.next(), args.next(), args.next()) {
+            (Some("name"), Some(name), Some(_), _) => {
+                // A formula may contain spaces, so take the rest of the line
+                let target = rest_of_line(rest_of_line(command));
+                match data.define_name(name, target) {
+                    Ok(()) => format!("Defined {} as {}", name, target),
+                    Err(e) => format!("Could not define {}: {}", name, e),
+                }
+            }
+            (Some("unname"), Some(name), None, None) => {
+                if data.delete_name(name) {
+                    format!("Deleted {}", name)
+                } else {
+                    format!("No name {}", name)
+                }
+            }
+            (Some("names"), None, None, None) => {
+                let names: Vec<_> = data
+                    .names()
+                    .iter()
+                    .map(|(name, target)| format!("{} = {}", name, target))
+                    .collect();
+                if names.is_empty() {
+                    "No names defined".to_string()
+                } else {
+                    names.join(", ")
+                }
+            }
+            (Some("sheet"), Some(_), _, _) => match data.sheet_id(rest_of_line(command)) {
+                Some(id) => {
+                    data.set_active_sheet(id);
+                    format!("Switched to {}", data.sheet(id).name())
+                }
+                None => format!("No sheet {}", rest_of_line(command)),
+            },
+            (Some("newsheet"), Some(_), _, _) => match data.add_sheet(rest_of_line(command)) {
+                Ok(id) => {
+                    data.set_active_sheet(id);
+                    format!("Added {}", data.sheet(id).name())
+                }
+                Err(e) => format!("Could not add sheet: {}", e.reason),
+            },
+            (Some("rename"), Some(_), _, _) => {
+                let id = data.active_sheet();
+                match data.rename_sheet(id, rest_of_line(command)) {
+                    Ok(()) => format!("Renamed to {}", data.sheet(id).name()),
+                    Err(e) => format!("Could not rename sheet: {}", e.reason),
+                }
+            }
+            (Some("delsheet"), None, None, None) => {
+                let name = data.sheet(data.active_sheet()).name().to_string();
+                match data.delete_sheet(data.active_sheet()) {
+                    Ok(()) => format!("Deleted {}", name),
+                    Err(e) => format!("Could not delete {}: {}", name, e.reason),
+                }
+            }
+            (Some("notation"), choice, None, None) => {
+                let notation = match choice.map(|c| c.to_ascii_lowercase()).as_deref() {
+                    Some("a1") => Some(Notation::A1),
+                    Some("r1c1") => Some(Notation::R1C1),
+                    Some(_) => None,
+                    None if data.notation() == Notation::A1 => Some(Notation::R1C1),
+                    None => Some(Notation::A1),
+                };
+                match notation {
+                    Some(notation) => {
+                        data.set_notation(notation);
+                        format!("Showing formulas in {:?} notation", notation)
+                    }
+                    None => format!("Unknown notation: {}", choice.unwrap_or_default()),
+                }
+            }
+            (Some("sheets"), None, None, None) => {
+                let names: Vec<_> = data.sheets().iter().map(|s| s.name()).collect();
+                names.join(", ")
+            }
+            _ => format!("Unknown command: {}", command),
+        };
+    }
+
     pub fn handle_event(&mut self, key: Key) {
         match self.val {
             StateVal::Normal => self.handle_event_normal(key),
             StateVal::Insert => self.handle_event_insert(key),
+            StateVal::Command => self.handle_event_command(key),
             _ => {}
         }
     }
 
     fn handle_event_normal(&mut self, key: Key) {
+        // A command's result stays on the status line until the next key,
+        // which lets the cell under the cursor report its error again.
+        self.status.clear();
         match key {
             Key::Char('q') => self.val = StateVal::Exit,
             Key::Down | Key::Char('j') => {
@@ -73,6 +197,25 @@ impl State {
                 self.buffer = String::new();
                 self.val = StateVal::Insert;
             }
+            Key::Char(':') => {
+                self.buffer = String::new();
+                self.val = StateVal::Command;
+            }
+            Key::Char('y') => {
+                self.clipboard = Some(self.cursor_pos);
+            }
+            Key::Char('p') => {
+                if let Some(from) = self.clipboard {
+                    self.data_updates
+                        .push(DataUpdate::Copy(from, self.cursor_pos));
+                }
+            }
+            Key::Char('F') => {
+                if let Some(from) = self.clipboard {
+                    let range = CellRange::new(from, self.cursor_pos);
+                    self.data_updates.push(DataUpdate::Fill(from, range));
+                }
+            }
             _ => {}
         }
     }
@@ -81,7 +224,27 @@ impl State {
         match key {
             Key::Char('\n') => {
                 self.data_updates
-                    .push((self.cursor_pos, self.buffer.clone()));
+                    .push(DataUpdate::Insert(self.cursor_pos, self.buffer.clone()));
+                self.val = StateVal::Normal;
+            }
+            Key::Char(x) => {
+                self.buffer.push(x);
+            }
+            Key::Backspace => {
+                let _ = self.buffer.pop();
+            }
+            Key::Esc => {
+                self.val = StateVal::Normal;
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_event_command(&mut self, key: Key) {
+        match key {
+            Key::Char('\n') => {
+                self.data_updates
+                    .push(DataUpdate::Command(self.buffer.clone()));
                 self.val = StateVal::Normal;
             }
             Key::Char(x) => {
@@ -113,3 +276,53 @@ impl State {
         self.cursor_pos.x += 1;
     }
 }
+
+/// Everything after the first word of a command, for arguments that may
+/// contain spaces.
+fn rest_of_line(command: &str) -> &str {
+    let command = command.trim_start();
+    let word = command.find(char::is_whitespace).unwrap_or(command.len());
+    command[word..].trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compositor::Compositor;
+    use crate::value::Value;
+    use tui::layout::Rect;
+
+    #[test]
+    fn test_commands() {
+        let mut state = State::new();
+        let mut data = Data::new();
+        state.run_command("\tname\tdouble  =LAMBDA(x, x * 2) ", &mut data);
+        assert_eq!(state.status, "Defined double as =LAMBDA(x, x * 2)");
+
+        state.run_command("newsheet\tMy Sheet", &mut data);
+        assert_eq!(state.status, "Added My Sheet");
+
+        data.insert(CellPos::new(0, 0), "=DOUBLE(4)".to_string());
+        assert_eq!(data.get(CellPos::new(0, 0)), Some(&Value::Number(8.0)));
+    }
+
+    #[test]
+    fn test_status_after_command() {
+        let mut state = State::new();
+        let mut data = Data::new();
+        data.insert(CellPos::new(0, 0), "=B1".to_string());
+        data.insert(CellPos::new(1, 0), "=A1".to_string());
+        state.run_command("sheets", &mut data);
+
+        let mut compositor = Compositor::new();
+        compositor.set_area(Rect::new(0, 0, 80, 24));
+        compositor.set_state(state.get_info());
+        compositor.set_data(&data);
+        assert_eq!(compositor.get_status(), "Sheet1");
+
+        state.handle_event(Key::Char('l'));
+        compositor.set_state(state.get_info());
+        compositor.set_data(&data);
+        assert_eq!(compositor.get_status(), "#CYCLE!: B1 → A1 → B1");
+    }
+}
@@ -0,0 +1,208 @@
+use crate::parse::LangError;
+use std::fmt;
+
+/// The powers of length, mass and time a quantity is measured in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Dimension([i32; 3]);
+
+const LENGTH: Dimension = Dimension([1, 0, 0]);
+const MASS: Dimension = Dimension([0, 1, 0]);
+const TIME: Dimension = Dimension([0, 0, 1]);
+
+/// The SI units the base dimensions are measured in.
+const BASE_SYMBOLS: [&str; 3] = ["m", "kg", "s"];
+
+impl Dimension {
+    /// The dimension of a plain number.
+    pub const NONE: Dimension = Dimension([0, 0, 0]);
+
+    pub fn times(self, other: Dimension) -> Result<Self, LangError> {
+        self.zip(other, i32::checked_add)
+    }
+
+    pub fn per(self, other: Dimension) -> Result<Self, LangError> {
+        self.zip(other, i32::checked_sub)
+    }
+
+    pub fn pow(self, n: i32) -> Result<Self, LangError> {
+        self.zip(Dimension::NONE, |a, _| a.checked_mul(n))
+    }
+
+    /// Combines the powers pairwise, failing with `#NUM!` when one
+    /// overflows.
+    fn zip(self, other: Dimension, f: impl Fn(i32, i32) -> Option<i32>) -> Result<Self, LangError> {
+        let Dimension([a, b, c]) = self;
+        let Dimension([x, y, z]) = other;
+        match (f(a, x), f(b, y), f(c, z)) {
+            (Some(a), Some(b), Some(c)) => Ok(Dimension([a, b, c])),
+            _ => Err(LangError::num("The powers of the unit are too large")),
+        }
+    }
+
+    /// What is measured, for error messages: "a length" or "a number".
+    fn describe(self) -> String {
+        match self {
+            Dimension::NONE => "a number".to_string(),
+            LENGTH => "a length".to_string(),
+            MASS => "a mass".to_string(),
+            TIME => "a time".to_string(),
+            dimension => format!("a quantity in {}", Unit::base(dimension)),
+        }
+    }
+}
+
+/// The units that can be written after a number, with their size in SI
+/// units. The grammar lists the same symbols.
+const UNITS: &[(&str, f64, Dimension)] = &[
+    ("m", 1.0, LENGTH),
+    ("mm", 0.001, LENGTH),
+    ("cm", 0.01, LENGTH),
+    ("km", 1000.0, LENGTH),
+    ("in", 0.0254, LENGTH),
+    ("ft", 0.3048, LENGTH),
+    ("yd", 0.9144, LENGTH),
+    ("mi", 1609.344, LENGTH),
+    ("kg", 1.0, MASS),
+    ("g", 0.001, MASS),
+    ("mg", 0.000_001, MASS),
+    ("lb", 0.453_592_37, MASS),
+    ("oz", 0.028_349_523_125, MASS),
+    ("s", 1.0, TIME),
+    ("ms", 0.001, TIME),
+    ("min", 60.0, TIME),
+    ("h", 3600.0, TIME),
+];
+
+/// A unit a number is measured in, such as `mm` or, for the results of
+/// multiplying quantities, `m^2` and `m/s`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unit {
+    pub symbol: String,
+    /// The size of the unit in SI units, 0.001 for `mm`.
+    pub factor: f64,
+    pub dimension: Dimension,
+}
+
+impl Unit {
+    /// Looks up a unit by its symbol, ignoring case.
+    pub fn find(symbol: &str) -> Option<Unit> {
+        UNITS
+            .iter()
+            .find(|(s, _, _)| s.eq_ignore_ascii_case(symbol))
+            .map(|(symbol, factor, dimension)| Unit {
+                symbol: symbol.to_string(),
+                factor: *factor,
+                dimension: *dimension,
+            })
+    }
+
+    /// Like [`Unit::find`], with an error naming the missing unit.
+    pub fn named(symbol: &str) -> Result<Unit, LangError> {
+        Unit::find(symbol).ok_or_else(|| LangError::na(format!("Unknown unit {}", symbol)))
+    }
+
+    /// The SI unit of a dimension, such as `m^2`, `m/s` or `kg*m/s^2`.
+    pub fn base(dimension: Dimension) -> Unit {
+        let part = |symbol: &str, power: i32| match power {
+            1 => symbol.to_string(),
+            p => format!("{}^{}", symbol, p),
+        };
+        let mut above = Vec::new();
+        let mut below = Vec::new();
+        let Dimension(powers) = dimension;
+        for (i, power) in powers.iter().enumerate() {
+            let symbol = BASE_SYMBOLS[i];
+            if *power > 0 {
+                above.push(part(symbol, *power));
+            } else if *power < 0 {
+                below.push(part(symbol, -power));
+            }
+        }
+
+        let mut symbol = if above.is_empty() {
+            "1".to_string()
+        } else {
+            above.join("*")
+        };
+        if below.len() == 1 {
+            symbol = format!("{}/{}", symbol, below[0]);
+        } else if !below.is_empty() {
+            symbol = format!("{}/({})", symbol, below.join("*"));
+        }
+        Unit {
+            symbol,
+            factor: 1.0,
+            dimension,
+        }
+    }
+
+    /// Converts `n` of this unit into `to`, which must measure the same
+    /// thing.
+    pub fn convert(&self, n: f64, to: &Unit) -> Result<f64, LangError> {
+        if self.dimension != to.dimension {
+            return Err(LangError::value(format!(
+                "Cannot convert {} ({}) to {} ({})",
+                self.symbol,
+                self.dimension.describe(),
+                to.symbol,
+                to.dimension.describe()
+            )));
+        }
+        Ok(n * self.factor / to.factor)
+    }
+
+    /// The error for adding or comparing values measuring different things.
+    pub fn mismatch(a: Dimension, b: Dimension) -> LangError {
+        LangError::value(format!(
+            "Cannot combine {} with {}",
+            a.describe(),
+            b.describe()
+        ))
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.symbol)
+    }
+}
+
+/// Splits input such as `5 kg` or `12mm` into its number and unit.
+pub fn parse_quantity(input: &str) -> Option<(f64, Unit)> {
+    let split = input.find(|c: char| c.is_ascii_alphabetic())?;
+    let n = input[..split].trim().parse::<f64>().ok()?;
+    let unit = Unit::find(input[split..].trim())?;
+    if n.is_finite() {
+        Some((n, unit))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_units() {
+        let mm = Unit::find("mm").unwrap();
+        let inch = Unit::find("IN").unwrap();
+        assert_eq!(inch.convert(1.0, &mm), Ok(25.4));
+        assert!(mm.convert(1.0, &Unit::find("kg").unwrap()).is_err());
+
+        let speed = LENGTH.per(TIME).unwrap();
+        assert_eq!(Unit::base(speed).symbol, "m/s");
+        assert_eq!(Unit::base(LENGTH.pow(2).unwrap()).symbol, "m^2");
+        assert_eq!(Unit::base(TIME.pow(-1).unwrap()).symbol, "1/s");
+        let force = MASS.times(LENGTH).and_then(|d| d.per(TIME.pow(2)?));
+        assert_eq!(Unit::base(force.unwrap()).symbol, "m*kg/s^2");
+        assert!(LENGTH.pow(2).unwrap().pow(i32::MAX).is_err());
+
+        assert_eq!(
+            parse_quantity("5 kg").map(|(n, u)| (n, u.symbol)),
+            Some((5.0, "kg".to_string()))
+        );
+        assert_eq!(parse_quantity("2.5mm").map(|(n, _)| n), Some(2.5));
+        assert_eq!(parse_quantity("5 in stock"), None);
+    }
+}
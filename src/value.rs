@@ -0,0 +1,473 @@
+use crate::ast::Expr;
+use crate::decimal::Decimal;
+use crate::parse::LangError;
+use crate::unit::{parse_quantity, Dimension, Unit};
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use std::cmp::Ordering;
+use std::fmt;
+use std::rc::Rc;
+
+/// The evaluated contents of a cell.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Value {
+    #[default]
+    Empty,
+    Number(f64),
+    /// An exact amount of money, entered as `$12.50`.
+    Decimal(Decimal),
+    /// A number measured in a unit, such as `12 mm`.
+    Quantity(f64, Unit),
+    Text(String),
+    Bool(bool),
+    Error(LangError),
+    Date(NaiveDate),
+    Time(NaiveTime),
+    DateTime(NaiveDateTime),
+    /// Rows of values, as produced by a range such as `A1:B10`.
+    Array(Vec<Vec<Value>>),
+    Lambda(Rc<Lambda>),
+}
+
+/// A function written in a formula with `LAMBDA(params, body)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lambda {
+    pub params: Vec<String>,
+    pub body: Rc<Expr>,
+    /// The LET variables visible where the lambda was written.
+    pub captured: Vec<(String, Value)>,
+}
+
+impl Value {
+    /// Interprets raw (non-formula) cell input.
+    pub fn from_input(input: &str) -> Self {
+        let trimmed = input.trim();
+        if trimmed.is_empty() {
+            return Value::Empty;
+        }
+
+        if let Ok(n) = trimmed.parse::<f64>() {
+            if n.is_finite() {
+                return Value::Number(n);
+            }
+        }
+
+        if let Some(d) = Decimal::parse_currency(trimmed) {
+            return Value::Decimal(d);
+        }
+        if let Some((n, unit)) = parse_quantity(trimmed) {
+            return Value::Quantity(n, unit);
+        }
+
+        match trimmed.to_ascii_uppercase().as_str() {
+            "TRUE" => Value::Bool(true),
+            "FALSE" => Value::Bool(false),
+            _ => parse_datetime(trimmed).unwrap_or_else(|| Value::Text(input.to_string())),
+        }
+    }
+
+    /// Builds a date from a serial number of days since 1899-12-30, the
+    /// fraction giving the time of day. Whole numbers become plain dates.
+    pub fn from_serial(serial: f64) -> Result<Self, LangError> {
+        let datetime =
+            serial_to_datetime(serial).ok_or_else(|| LangError::num("The date is out of range"))?;
+        if datetime.time() == NaiveTime::from_hms(0, 0, 0) {
+            Ok(Value::Date(datetime.date()))
+        } else {
+            Ok(Value::DateTime(datetime))
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            Value::Number(_)
+                | Value::Decimal(_)
+                | Value::Quantity(_, _)
+                | Value::Date(_)
+                | Value::Time(_)
+                | Value::DateTime(_)
+        )
+    }
+
+    /// Coerces to a number the way arithmetic operators do: blanks are 0,
+    /// booleans are 1/0, dates are serial numbers and text must look like a
+    /// number or a date. Quantities are refused rather than losing their
+    /// unit.
+    pub fn as_number(&self) -> Result<f64, LangError> {
+        match self {
+            Value::Empty => Ok(0.0),
+            Value::Number(n) => Ok(*n),
+            Value::Quantity(_, _) => Err(LangError::value(format!(
+                "{} has a unit, so it is not a plain number",
+                self
+            ))),
+            Value::Decimal(d) => Ok(d.to_f64()),
+            Value::Bool(b) => Ok(if *b { 1.0 } else { 0.0 }),
+            Value::Text(s) => match s.trim().parse::<f64>() {
+                Ok(n) => Ok(n),
+                Err(_) => match parse_datetime(s.trim()) {
+                    Some(val) => val.as_number(),
+                    None => Err(LangError::value(format!("\"{}\" is not a number", s))),
+                },
+            },
+            Value::Date(date) => Ok(datetime_to_serial(date.and_hms(0, 0, 0))),
+            Value::Time(time) => Ok(time.num_seconds_from_midnight() as f64 / SECONDS_PER_DAY),
+            Value::DateTime(datetime) => Ok(datetime_to_serial(*datetime)),
+            Value::Error(e) => Err(e.clone()),
+            Value::Array(_) | Value::Lambda(_) => Err(self.not_scalar()),
+        }
+    }
+
+    /// Coerces to a date and time. Numbers are taken as serial numbers and
+    /// times fall on the first serial day.
+    pub fn as_datetime(&self) -> Result<NaiveDateTime, LangError> {
+        match self {
+            Value::DateTime(datetime) => Ok(*datetime),
+            Value::Date(date) => Ok(date.and_hms(0, 0, 0)),
+            Value::Bool(_) => Err(LangError::value("A boolean is not a date")),
+            v => serial_to_datetime(v.as_number()?)
+                .ok_or_else(|| LangError::num("The date is out of range")),
+        }
+    }
+
+    /// Coerces to an exact decimal, rounding numbers to four decimals.
+    pub fn as_decimal(&self) -> Result<Decimal, LangError> {
+        match self {
+            Value::Decimal(d) => Ok(*d),
+            v => Decimal::from_f64(v.as_number()?),
+        }
+    }
+
+    pub fn as_date(&self) -> Result<NaiveDate, LangError> {
+        self.as_datetime().map(|datetime| datetime.date())
+    }
+
+    pub fn as_bool(&self) -> Result<bool, LangError> {
+        match self {
+            Value::Empty => Ok(false),
+            Value::Number(n) | Value::Quantity(n, _) => Ok(*n != 0.0),
+            Value::Decimal(d) => Ok(*d != Decimal::default()),
+            Value::Date(_) | Value::Time(_) | Value::DateTime(_) => Ok(self.as_number()? != 0.0),
+            Value::Bool(b) => Ok(*b),
+            Value::Text(s) => match s.trim().to_ascii_uppercase().as_str() {
+                "TRUE" => Ok(true),
+                "FALSE" => Ok(false),
+                _ => Err(LangError::value(format!("\"{}\" is not TRUE or FALSE", s))),
+            },
+            Value::Error(e) => Err(e.clone()),
+            Value::Array(_) | Value::Lambda(_) => Err(self.not_scalar()),
+        }
+    }
+
+    pub fn as_text(&self) -> Result<String, LangError> {
+        match self {
+            Value::Error(e) => Err(e.clone()),
+            Value::Array(_) | Value::Lambda(_) => Err(self.not_scalar()),
+            v => Ok(v.to_string()),
+        }
+    }
+
+    /// Orders two values the way comparison operators do. Numbers and
+    /// dates sort before text, which sorts before booleans, and text is compared
+    /// without regard to case. Blanks act as 0 or "" depending on the
+    /// other side. Quantities are only compared with quantities measuring
+    /// the same thing.
+    pub fn compare(&self, other: &Value) -> Result<Ordering, LangError> {
+        fn rank(val: &Value) -> u8 {
+            match val {
+                v if v.is_numeric() => 0,
+                Value::Text(_) => 1,
+                _ => 2,
+            }
+        }
+
+        match (self, other) {
+            (Value::Error(e), _) | (_, Value::Error(e)) => Err(e.clone()),
+            (v @ Value::Array(_), _) | (_, v @ Value::Array(_)) => Err(v.not_scalar()),
+            (v @ Value::Lambda(_), _) | (_, v @ Value::Lambda(_)) => Err(v.not_scalar()),
+            (Value::Empty, Value::Empty) => Ok(Ordering::Equal),
+            (Value::Empty, Value::Quantity(_, unit)) => {
+                Value::Quantity(0.0, unit.clone()).compare(other)
+            }
+            (Value::Empty, v) if v.is_numeric() => Value::Number(0.0).compare(other),
+            (Value::Empty, Value::Text(_)) => Value::Text(String::new()).compare(other),
+            (Value::Empty, Value::Bool(_)) => Value::Bool(false).compare(other),
+            (_, Value::Empty) => other.compare(self).map(Ordering::reverse),
+            (Value::Decimal(a), Value::Decimal(b)) => Ok(a.cmp(b)),
+            (Value::Quantity(a, unit), Value::Quantity(b, other_unit)) => {
+                let b = other_unit.convert(*b, unit)?;
+                Ok(a.partial_cmp(&b).unwrap_or(Ordering::Equal))
+            }
+            (Value::Quantity(_, unit), v) | (v, Value::Quantity(_, unit)) if v.is_numeric() => {
+                Err(Unit::mismatch(unit.dimension, Dimension::NONE))
+            }
+            (a, b) if a.is_numeric() && b.is_numeric() => {
+                let (a, b) = (a.as_number()?, b.as_number()?);
+                Ok(a.partial_cmp(&b).unwrap_or(Ordering::Equal))
+            }
+            (Value::Text(a), Value::Text(b)) => Ok(a.to_lowercase().cmp(&b.to_lowercase())),
+            (Value::Bool(a), Value::Bool(b)) => Ok(a.cmp(b)),
+            (a, b) => Ok(rank(a).cmp(&rank(b))),
+        }
+    }
+
+    /// The error for using a range or lambda where one value is needed.
+    fn not_scalar(&self) -> LangError {
+        match self {
+            Value::Lambda(_) => LangError::value("A LAMBDA must be called to give a value"),
+            _ => LangError::value("A range cannot be used as a single value"),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Value::Empty
+    }
+
+    /// Applies `f` to every element of an array, or to a scalar itself.
+    pub fn map(&self, f: impl Fn(&Value) -> Value) -> Value {
+        match self {
+            Value::Array(rows) => Value::Array(
+                rows.iter()
+                    .map(|row| row.iter().map(&f).collect())
+                    .collect(),
+            ),
+            v => f(v),
+        }
+    }
+
+    /// Pairs up the elements of two values of which at least one is an
+    /// array. A single row or column is repeated to match the other side,
+    /// and elements missing from the smaller side are `#N/A`.
+    pub fn broadcast(&self, other: &Value, f: impl Fn(&Value, &Value) -> Value) -> Value {
+        fn size(val: &Value) -> (usize, usize) {
+            match val {
+                Value::Array(rows) => (rows.len(), rows.iter().map(Vec::len).max().unwrap_or(0)),
+                _ => (1, 1),
+            }
+        }
+
+        fn missing() -> LangError {
+            LangError::na("The arrays are different sizes")
+        }
+
+        fn element(val: &Value, row: usize, col: usize) -> Value {
+            let rows = match val {
+                Value::Array(rows) => rows,
+                v => return v.clone(),
+            };
+            let row = if rows.len() == 1 { 0 } else { row };
+            match rows.get(row) {
+                Some(cells) => {
+                    let col = if cells.len() == 1 { 0 } else { col };
+                    cells
+                        .get(col)
+                        .cloned()
+                        .unwrap_or_else(|| Value::Error(missing()))
+                }
+                None => Value::Error(missing()),
+            }
+        }
+
+        let ((h1, w1), (h2, w2)) = (size(self), size(other));
+        let rows = (0..h1.max(h2))
+            .map(|r| {
+                (0..w1.max(w2))
+                    .map(|c| f(&element(self, r, c), &element(other, r, c)))
+                    .collect()
+            })
+            .collect();
+        Value::Array(rows)
+    }
+
+    /// Iterates over a value as if it were an array, so a scalar yields itself.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = &Value> + '_> {
+        match self {
+            Value::Array(rows) => Box::new(rows.iter().flatten()),
+            v => Box::new(std::iter::once(v)),
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::Text(s)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Empty => Ok(()),
+            Value::Number(n) => write!(f, "{}", format_number(*n)),
+            Value::Decimal(d) => write!(f, "{}", d),
+            Value::Quantity(n, unit) => write!(f, "{} {}", format_number(*n), unit),
+            Value::Text(s) => write!(f, "{}", s),
+            Value::Bool(true) => write!(f, "TRUE"),
+            Value::Bool(false) => write!(f, "FALSE"),
+            Value::Error(e) => write!(f, "{}", e),
+            Value::Date(date) => write!(f, "{}", date.format("%Y-%m-%d")),
+            Value::Time(time) => write!(f, "{}", time.format(time_format(time))),
+            Value::DateTime(datetime) => {
+                let format = format!("%Y-%m-%d {}", time_format(&datetime.time()));
+                write!(f, "{}", datetime.format(&format))
+            }
+            Value::Array(rows) => match rows.first().and_then(|r| r.first()) {
+                Some(v) => write!(f, "{}", v),
+                None => Ok(()),
+            },
+            Value::Lambda(_) => write!(f, "LAMBDA"),
+        }
+    }
+}
+
+const SECONDS_PER_DAY: f64 = 86_400.0;
+
+/// Day 0 of serial numbers, as used by other spreadsheets.
+fn epoch() -> NaiveDateTime {
+    NaiveDate::from_ymd(1899, 12, 30).and_hms(0, 0, 0)
+}
+
+fn datetime_to_serial(datetime: NaiveDateTime) -> f64 {
+    let elapsed = datetime.signed_duration_since(epoch());
+    elapsed.num_seconds() as f64 / SECONDS_PER_DAY
+}
+
+fn serial_to_datetime(serial: f64) -> Option<NaiveDateTime> {
+    let seconds = (serial * SECONDS_PER_DAY).round();
+    if !seconds.is_finite() || seconds.abs() > 1e13 {
+        return None;
+    }
+    epoch().checked_add_signed(Duration::seconds(seconds as i64))
+}
+
+/// Recognises dates like `2026-10-18`, times like `14:30` and both
+/// together like `2026-10-18 14:30`.
+fn parse_datetime(input: &str) -> Option<Value> {
+    let time = |s: &str| {
+        NaiveTime::parse_from_str(s, "%H:%M:%S")
+            .or_else(|_| NaiveTime::parse_from_str(s, "%H:%M"))
+            .ok()
+    };
+
+    if let Ok(date) = NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+        return Some(Value::Date(date));
+    }
+    if let Some(time) = time(input) {
+        return Some(Value::Time(time));
+    }
+
+    let split = input.find([' ', 'T'])?;
+    let date = NaiveDate::parse_from_str(&input[..split], "%Y-%m-%d").ok()?;
+    let time = time(input[split + 1..].trim())?;
+    Some(Value::DateTime(date.and_time(time)))
+}
+
+fn time_format(time: &NaiveTime) -> &'static str {
+    if time.second() == 0 {
+        "%H:%M"
+    } else {
+        "%H:%M:%S"
+    }
+}
+
+/// Whole numbers are shown without a trailing `.0`.
+pub fn format_number(n: f64) -> String {
+    if n.fract() == 0.0 && n.abs() < 1e15 {
+        format!("{}", n as i64)
+    } else {
+        format!("{}", n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::ErrorKind;
+
+    #[test]
+    fn test_from_input() {
+        assert_eq!(Value::from_input(""), Value::Empty);
+        assert_eq!(Value::from_input("2.5"), Value::Number(2.5));
+        assert_eq!(Value::from_input("true"), Value::Bool(true));
+        assert_eq!(Value::from_input("abc"), Value::Text("abc".to_string()));
+        assert_eq!(Value::from_input("$1,250.5").to_string(), "1,250.50");
+
+        let date = NaiveDate::from_ymd(2026, 10, 18);
+        assert_eq!(Value::from_input("2026-10-18"), Value::Date(date));
+        assert_eq!(
+            Value::from_input("14:30"),
+            Value::Time(NaiveTime::from_hms(14, 30, 0))
+        );
+        assert_eq!(
+            Value::from_input("2026-10-18 14:30:15"),
+            Value::DateTime(date.and_hms(14, 30, 15))
+        );
+        assert_eq!(
+            Value::from_input("2026-13-01"),
+            Value::Text("2026-13-01".to_string())
+        );
+    }
+
+    #[test]
+    fn test_dates() {
+        let date = Value::from_input("2026-10-18");
+        assert_eq!(date.as_number(), Ok(46313.0));
+        assert_eq!(date.to_string(), "2026-10-18");
+        assert_eq!(
+            Value::from_serial(46313.75),
+            Ok(Value::from_input("2026-10-18 18:00"))
+        );
+        assert_eq!(Value::from_input("06:00").as_number(), Ok(0.25));
+        assert_eq!(Value::from_input("06:00:30").to_string(), "06:00:30");
+        assert_eq!(
+            Value::Text("2026-10-19".to_string()).as_number(),
+            Ok(46314.0)
+        );
+        assert_eq!(date.compare(&Value::Number(46314.0)), Ok(Ordering::Less));
+    }
+
+    #[test]
+    fn test_compare() {
+        let text = |s: &str| Value::Text(s.to_string());
+        assert_eq!(
+            Value::Number(1.0).compare(&Value::Number(2.0)),
+            Ok(Ordering::Less)
+        );
+        assert_eq!(text("abc").compare(&text("ABC")), Ok(Ordering::Equal));
+        assert_eq!(Value::Number(99.0).compare(&text("1")), Ok(Ordering::Less));
+        assert_eq!(text("z").compare(&Value::Bool(false)), Ok(Ordering::Less));
+        assert_eq!(
+            Value::Empty.compare(&Value::Number(0.0)),
+            Ok(Ordering::Equal)
+        );
+        assert_eq!(text("").compare(&Value::Empty), Ok(Ordering::Equal));
+        assert_eq!(
+            Value::Number(-1.0).compare(&Value::Empty),
+            Ok(Ordering::Less)
+        );
+    }
+
+    #[test]
+    fn test_coercion() {
+        assert_eq!(Value::Empty.as_number(), Ok(0.0));
+        assert_eq!(Value::Text(" 4 ".to_string()).as_number(), Ok(4.0));
+        assert_eq!(
+            Value::Text("four".to_string()).as_number(),
+            Err(ErrorKind::Value.into())
+        );
+        assert_eq!(Value::Number(3.0).to_string(), "3");
+        assert_eq!(Value::Number(0.25).to_string(), "0.25");
+    }
+}
@@ -1,6 +1,6 @@
 use tui::buffer::Buffer;
 use tui::layout::Rect;
-use tui::style::{Color, Modifier, Style};
+use tui::style::Style;
 use tui::symbols::line;
 use tui::widgets::Widget;
 